@@ -1,7 +1,7 @@
 // 64-bit magic bitscan
 
-const bitscan_magic: u64 = 0x7edd5e59a4e28c2;
-const bitscan_db: [u64; 64] = [
+const BITSCAN_MAGIC: u64 = 0x7edd5e59a4e28c2;
+const BITSCAN_DB: [u64; 64] = [
     63, 0, 58, 1, 59, 47, 53, 2, 60, 39, 48, 27, 54, 33, 42, 3,
     61, 51, 37, 40, 49, 18, 28, 20, 55, 30, 34, 11, 43, 14, 22, 4,
     62, 57, 46, 52, 38, 26, 32, 41, 50, 36, 17, 19, 29, 10, 13, 21,
@@ -9,8 +9,25 @@ const bitscan_db: [u64; 64] = [
 ];
 
 pub fn bitscan(i: u64) -> u64 {
-    let index = i.wrapping_mul(bitscan_magic) >> (64 - 6);
-    bitscan_db[index as usize]
+    let index = i.wrapping_mul(BITSCAN_MAGIC) >> (64 - 6);
+    BITSCAN_DB[index as usize]
+}
+
+// Iterate over the indices of all set bits, starting at the LSB
+pub struct BitIter(pub u64);
+
+impl Iterator for BitIter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            let lsb = self.0 & self.0.wrapping_neg();
+            self.0 ^= lsb;
+            Some(bitscan(lsb) as usize)
+        }
+    }
 }
 
 #[test]
@@ -19,3 +36,9 @@ fn bitscan_test() {
         assert_eq!(bitscan(1 << i), i);
     }
 }
+
+#[test]
+fn bit_iter_test() {
+    let squares: Vec<usize> = BitIter(0x8000_0000_0000_0105).collect();
+    assert_eq!(squares, vec![0, 2, 8, 63]);
+}
@@ -1,4 +1,8 @@
 use std::fmt;
+use movegen;
+use bitscan::bitscan;
+use zobrist;
+use eval::{self, Score};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
@@ -18,7 +22,16 @@ pub enum Color {
 }
 use self::Color::*;
 
-#[derive(Debug)]
+impl Color {
+    pub fn opponent(&self) -> Color {
+        match *self {
+            White => Black,
+            Black => White,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Direction(pub i64, pub i64);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,6 +52,39 @@ impl Position {
         self.file <= 7 && self.file >= 0 &&
         self.rank <= 7 && self.rank >= 0
     }
+
+    // Bitboard square index, A1 -> 0, ..., H8 -> 63
+    pub fn square(&self) -> usize {
+        (self.rank * 8 + self.file) as usize
+    }
+
+    pub fn from_square(square: usize) -> Self {
+        Self::new((square % 8) as i64, (square / 8) as i64)
+    }
+
+    pub fn mask(&self) -> u64 {
+        1 << self.square()
+    }
+
+    // Parse squares like "e4" or "E4"
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut chars = s.chars();
+        let file = match chars.next() {
+            Some(c @ 'a'..='h') => (c as u8 - b'a') as i64,
+            Some(c @ 'A'..='H') => (c as u8 - b'A') as i64,
+            _ => return None,
+        };
+        let rank = match chars.next() {
+            Some(c @ '1'..='8') => (c as u8 - b'1') as i64,
+            _ => return None,
+        };
+
+        if chars.next().is_some() {
+            None
+        } else {
+            Some(Self::new(file, rank))
+        }
+    }
 }
 
 impl fmt::Display for Position {
@@ -48,7 +94,7 @@ impl fmt::Display for Position {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     pub from: Position,
     pub to: Position,
@@ -62,23 +108,23 @@ pub struct Move {
 impl Move {
     pub fn normal(from: &Position, to: &Position) -> Self {
         Self {
-            from: from.clone(),
-            to: to.clone(),
+            from: *from,
+            to: *to,
             promotion: None,
             // en_passant_square: None,
             // en_passant_capture: None
         }
     }
 
-    // pub fn promotion(from: &Position, to: &Position, kind: Type) -> Self {
-    //     Self {
-    //         from: from.clone(),
-    //         to: to.clone(),
-    //         promotion: Some(kind),
-    //         en_passant_square: None,
-    //         en_passant_capture: None
-    //     }
-    // }
+    pub fn promotion(from: &Position, to: &Position, kind: Type) -> Self {
+        Self {
+            from: *from,
+            to: *to,
+            promotion: Some(kind),
+            // en_passant_square: None,
+            // en_passant_capture: None
+        }
+    }
 
     // // Here en passant referes to a move that __creates__ an en passant square
     // pub fn en_passant(from: &Position, to: &Position, ep: &Position) -> Self {
@@ -90,6 +136,15 @@ impl Move {
     //         en_passant_capture: None
     //     }
     // }
+
+    // Long algebraic notation as used by UCI, e.g. "e2e4" or "e7e8q"
+    pub fn to_uci(self) -> String {
+        let mut res = format!("{}{}", self.from, self.to).to_lowercase();
+        if let Some(kind) = self.promotion {
+            res += &Piece::new(kind, Black).to_string();
+        }
+        res
+    }
 }
 
 impl fmt::Display for Move {
@@ -112,11 +167,11 @@ pub struct Piece {
 }
 
 impl Piece {
-    fn new(kind: Type, color: Color) -> Self {
+    pub fn new(kind: Type, color: Color) -> Self {
         Self{kind, color}
     }
 
-    fn from_fen(fen: char) -> Self {
+    pub fn from_fen(fen: char) -> Option<Self> {
         let t = match fen {
             'k' | 'K' => King,
             'q' | 'Q' => Queen,
@@ -124,13 +179,13 @@ impl Piece {
             'b' | 'B' => Bishop,
             'n' | 'N' => Knight,
             'p' | 'P' => Pawn,
-            _ => panic!("Not a valid fen char: '{}'", fen),
+            _ => return None,
         };
 
         if fen.is_uppercase() {
-            Self::new(t, White)
+            Some(Self::new(t, White))
         } else {
-            Self::new(t, Black)
+            Some(Self::new(t, Black))
         }
     }
 }
@@ -154,54 +209,101 @@ impl fmt::Display for Piece {
     }
 }
 
-#[derive(Copy)]
+// Castling rights are stored as a bitmask,
+// one bit per letter of the FEN castling field (KQkq)
+pub const CASTLE_WHITE_KING: u8 = 1;
+pub const CASTLE_WHITE_QUEEN: u8 = 2;
+pub const CASTLE_BLACK_KING: u8 = 4;
+pub const CASTLE_BLACK_QUEEN: u8 = 8;
+pub const CASTLE_ALL: u8 = 15;
+
+// Rights that are lost if a piece moves from or to `pos`,
+// either because the king or rook moved or because the rook was captured
+fn castling_rights_lost(pos: &Position) -> u8 {
+    match (pos.file, pos.rank) {
+        (0, 0) => CASTLE_WHITE_QUEEN,
+        (7, 0) => CASTLE_WHITE_KING,
+        (4, 0) => CASTLE_WHITE_KING | CASTLE_WHITE_QUEEN,
+        (0, 7) => CASTLE_BLACK_QUEEN,
+        (7, 7) => CASTLE_BLACK_KING,
+        (4, 7) => CASTLE_BLACK_KING | CASTLE_BLACK_QUEEN,
+        _ => 0,
+    }
+}
+
+// Castling is encoded as a king move of two files,
+// the rook jumps over to the other side of the king
+fn is_castling(m: &Move) -> bool {
+    (m.to.file - m.from.file).abs() == 2
+}
+
+fn castling_rook_squares(m: &Move) -> (Position, Position) {
+    if m.to.file == 6 {
+        (Position::new(7, m.from.rank), Position::new(5, m.from.rank))
+    } else {
+        (Position::new(0, m.from.rank), Position::new(3, m.from.rank))
+    }
+}
+
+// State that can't be recovered from a move alone,
+// returned by `make_move` and needed to take the move back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    pub captured: Option<Piece>,
+    pub en_passant: Option<Position>,
+    pub castling: u8,
+    pub halfmove_clock: u32,
+    pub hash: u64,
+}
+
+// Pieces are stored both as bitboards per type and per color,
+// indexed by `Type as usize` and `Color as usize`,
+// and as a mailbox for fast lookups of single squares
+#[derive(Copy, Debug, PartialEq, Eq)]
 pub struct Board {
-    pieces: [Option<Piece>; 64],
+    mailbox: [Option<Piece>; 64],
+    type_masks: [u64; 6],
+    color_masks: [u64; 2],
     pub turn: Color,
-    pub en_passant_white: Option<Position>,
-    pub en_passant_black: Option<Position>,
+    pub castling: u8,
+    // Square behind a pawn that just moved two squares,
+    // the target for en passant captures
+    pub en_passant: Option<Position>,
+    // Plies since the last capture or pawn move, for the 50 move rule
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    // Zobrist key, see zobrist.rs
+    pub hash: u64,
+    // Zobrist key of the pawns alone, for the pawn hash table
+    pub pawn_hash: u64,
+    // Material and piece-square values, see eval/psqt.rs
+    pub psqt: Score,
 }
 
 impl Board {
     pub fn empty() -> Board {
         Board {
-            pieces: [None; 64],
+            mailbox: [None; 64],
+            type_masks: [0; 6],
+            color_masks: [0; 2],
             turn: Color::White,
-            en_passant_white: None,
-            en_passant_black: None,
+            castling: 0,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: zobrist::castling(0),
+            pawn_hash: 0,
+            psqt: Score::default(),
         }
     }
 
-    pub fn from_fen(fen: &str) -> Board {
-        let mut board = Board::empty();
-        let mut row: usize = 0;
-        let mut col: usize = 0;
-
-        for c in fen.chars() {
-            match c {
-                '/' => {
-                    row += 1;
-                    col = 0;
-                }
-                '0'...'8' => {
-                    let offset = c.to_digit(10).unwrap() as usize;
-                    col += offset;
-                }
-                ' ' => return board,
-                other => {
-                    let piece = Piece::from_fen(other);
-                    board.set(col, 7 - row, Some(piece));
-                    col += 1;
-                }
-            }
-        }
-
-        board
+    pub fn can_castle(&self, right: u8) -> bool {
+        self.castling & right != 0
     }
 
     pub fn starting_position() -> Board {
-        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1)";
-        Self::from_fen(fen)
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        Self::from_fen(fen).unwrap()
     }
 
     pub fn get_pos(&self, pos: &Position) -> &Option<Piece> {
@@ -213,67 +315,212 @@ impl Board {
     }
 
     pub fn get(&self, col: usize, row: usize) -> &Option<Piece> {
-        self.pieces.get(row * 8 + col).unwrap()
+        self.get_square(row * 8 + col)
     }
 
     pub fn set(&mut self, col: usize, row: usize, piece: Option<Piece>) {
-        self.pieces[row * 8 + col] = piece;
-    }
-
-    pub fn make_move(&mut self, m: &Move) {
-        match *self.get_pos(&m.from) {
-            Some(piece) => {
-                self.set_pos(&m.to, Some(piece));
-                self.set_pos(&m.from, None);
-
-                // Handle moves that set en passant squares
-                if piece.color == Color::White {
-                    if piece.kind == Type::Pawn && m.from.rank == 1 && m.to.rank == 3 {
-                        self.en_passant_white = Some(Position::new(m.from.file, 2));
-                    } else {
-                        self.en_passant_white = None;
-                    }
-                } else {
-                    if piece.kind == Type::Pawn && m.from.rank == 6 && m.to.rank == 4 {
-                        self.en_passant_black = Some(Position::new(m.from.file, 2));
-                    } else {
-                        self.en_passant_black = None;
-                    }
-                }
+        self.set_square(row * 8 + col, piece);
+    }
 
-                // Handle moves that use en passant squares
-                if piece.color == Color::White {
-                    if piece.kind == Type::Pawn && Some(m.to) == self.en_passant_black {
-                        let beaten = Position::new(m.to.file, m.to.rank + 1);
-                        self.set_pos(&beaten, None);
-                    }
-                } else {
-                    if piece.kind == Type::Pawn && Some(m.to) == self.en_passant_white {
-                        let beaten = Position::new(m.to.file, m.to.rank - 1);
-                        self.set_pos(&beaten, None);
-                    }
-                }
+    pub fn get_square(&self, square: usize) -> &Option<Piece> {
+        &self.mailbox[square]
+    }
 
-            },
-            None => {
-                panic!("Can't make move {}, there is no piece at {}", m, m.from);
+    // Keep bitboards and mailbox in sync
+    pub fn set_square(&mut self, square: usize, piece: Option<Piece>) {
+        let mask = 1 << square;
+
+        if let Some(old) = self.mailbox[square] {
+            self.type_masks[old.kind as usize] ^= mask;
+            self.color_masks[old.color as usize] ^= mask;
+            self.hash ^= zobrist::piece(old, square);
+            if old.kind == Type::Pawn {
+                self.pawn_hash ^= zobrist::piece(old, square);
+            }
+            self.psqt -= eval::psqt::piece_square(old, square);
+        }
+
+        if let Some(new) = piece {
+            self.type_masks[new.kind as usize] |= mask;
+            self.color_masks[new.color as usize] |= mask;
+            self.hash ^= zobrist::piece(new, square);
+            if new.kind == Type::Pawn {
+                self.pawn_hash ^= zobrist::piece(new, square);
+            }
+            self.psqt += eval::psqt::piece_square(new, square);
+        }
+
+        self.mailbox[square] = piece;
+    }
+
+    pub fn type_mask(&self, kind: Type) -> u64 {
+        self.type_masks[kind as usize]
+    }
+
+    pub fn color_mask(&self, color: Color) -> u64 {
+        self.color_masks[color as usize]
+    }
+
+    pub fn piece_mask(&self, kind: Type, color: Color) -> u64 {
+        self.type_masks[kind as usize] & self.color_masks[color as usize]
+    }
+
+    pub fn make_move(&mut self, m: &Move) -> Undo {
+        // En passant is only possible for a single ply
+        let en_passant = self.en_passant.take();
+
+        let piece = match *self.get_pos(&m.from) {
+            Some(piece) => piece,
+            None => panic!("Can't make move {}, there is no piece at {}", m, m.from),
+        };
+
+        // Handle moves that use en passant squares,
+        // the captured pawn is next to the moving one
+        let captured_at = if piece.kind == Type::Pawn && Some(m.to) == en_passant {
+            Position::new(m.to.file, m.from.rank)
+        } else {
+            m.to
+        };
+
+        let undo = Undo {
+            captured: *self.get_pos(&captured_at),
+            en_passant,
+            castling: self.castling,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+
+        if let Some(square) = en_passant {
+            self.hash ^= zobrist::en_passant(square.file);
+        }
+
+        if piece.kind == Type::Pawn || undo.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if piece.color == Color::Black {
+            self.fullmove_number += 1;
+        }
+
+        self.set_pos(&captured_at, None);
+        match m.promotion {
+            Some(kind) => self.set_pos(&m.to, Some(Piece::new(kind, piece.color))),
+            None => self.set_pos(&m.to, Some(piece)),
+        }
+        self.set_pos(&m.from, None);
+
+        if piece.kind == Type::King && is_castling(m) {
+            let (rook_from, rook_to) = castling_rook_squares(m);
+            let rook = *self.get_pos(&rook_from);
+            self.set_pos(&rook_to, rook);
+            self.set_pos(&rook_from, None);
+        }
+
+        self.hash ^= zobrist::castling(self.castling);
+        self.castling &= !(castling_rights_lost(&m.from) | castling_rights_lost(&m.to));
+        self.hash ^= zobrist::castling(self.castling);
+
+        // Handle moves that set en passant squares,
+        // only if an enemy pawn can legally use it
+        if piece.kind == Type::Pawn && (m.to.rank - m.from.rank).abs() == 2 {
+            let square = Position::new(m.from.file, (m.from.rank + m.to.rank) / 2);
+            if self.en_passant_capturable(&m.to, &square) {
+                self.en_passant = Some(square);
+                self.hash ^= zobrist::en_passant(square.file);
             }
         }
-        if self.turn == Color::White {
-            self.turn = Color::Black;
+
+        self.turn = self.turn.opponent();
+        self.hash ^= zobrist::black_to_move();
+
+        debug_assert_eq!(self.hash, zobrist::hash(self), "hash mismatch after {}", m);
+        debug_assert_eq!(self.pawn_hash, zobrist::pawn_hash(self), "pawn hash mismatch after {}", m);
+        debug_assert_eq!(self.psqt, eval::psqt::psqt(self), "psqt mismatch after {}", m);
+        undo
+    }
+
+    // Take back `m`, which must be the last move made on this board
+    pub fn unmake_move(&mut self, m: &Move, undo: &Undo) {
+        self.turn = self.turn.opponent();
+        if self.turn == Color::Black {
+            self.fullmove_number -= 1;
+        }
+
+        let moved = match *self.get_pos(&m.to) {
+            Some(piece) => piece,
+            None => panic!("Can't unmake move {}, there is no piece at {}", m, m.to),
+        };
+        let piece = if m.promotion.is_some() { Piece::new(Type::Pawn, moved.color) } else { moved };
+
+        self.set_pos(&m.to, None);
+        self.set_pos(&m.from, Some(piece));
+
+        if piece.kind == Type::Pawn && Some(m.to) == undo.en_passant {
+            self.set_pos(&Position::new(m.to.file, m.from.rank), undo.captured);
         } else {
-            self.turn = Color::White;
+            self.set_pos(&m.to, undo.captured);
+        }
+
+        if piece.kind == Type::King && is_castling(m) {
+            let (rook_from, rook_to) = castling_rook_squares(m);
+            let rook = *self.get_pos(&rook_to);
+            self.set_pos(&rook_from, rook);
+            self.set_pos(&rook_to, None);
         }
+
+        self.en_passant = undo.en_passant;
+        self.castling = undo.castling;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
     }
 
-    pub fn occupancy(&self) -> u64 {
-        let mut mask = 0_u64;
-        for i in 0..64 {
-            if self.pieces[i].is_some() {
-                mask |= 1 << i;
+    // Check if the pawn that just moved two squares to `pawn`, passing
+    // `square`, can be captured en passant by an adjacent enemy pawn
+    // without leaving the enemy king in check
+    pub fn en_passant_capturable(&self, pawn: &Position, square: &Position) -> bool {
+        let color = match *self.get_pos(pawn) {
+            Some(piece) => piece.color,
+            None => return false,
+        };
+        let enemy = Some(Piece::new(Type::Pawn, color.opponent()));
+
+        [-1, 1].iter().any(|df| {
+            let from = Position::new(pawn.file + df, pawn.rank);
+            if !from.is_valid() || *self.get_pos(&from) != enemy {
+                return false;
             }
+            let mut board = *self;
+            board.en_passant = Some(*square);
+            board.hash = zobrist::hash(&board);
+            board.make_move(&Move::normal(&from, square));
+            !board.is_in_check(color.opponent())
+        })
+    }
+
+    pub fn king_position(&self, color: Color) -> Option<Position> {
+        let king = self.piece_mask(King, color);
+        if king == 0 {
+            None
+        } else {
+            Some(Position::from_square(bitscan(king) as usize))
+        }
+    }
+
+    // Check if `pos` is attacked by any piece of color `by`
+    pub fn is_square_attacked(&self, pos: Position, by: Color) -> bool {
+        movegen::is_attacked(&pos, self, by)
+    }
+
+    pub fn is_in_check(&self, color: Color) -> bool {
+        match self.king_position(color) {
+            Some(king) => self.is_square_attacked(king, color.opponent()),
+            None => false,
         }
-        mask
+    }
+
+    pub fn occupancy(&self) -> u64 {
+        self.color_masks[0] | self.color_masks[1]
     }
 }
 
@@ -293,7 +540,7 @@ impl fmt::Display for Board {
                 // Flip the board so that A1 (0, 0) is the lower left corner
                 match *self.get(col, 7 - row) {
                     Some(p) => res.push_str(&p.to_string()),
-                    None => res.push_str(" "),
+                    None => res.push(' '),
                 }
             }
             res.push_str("║\n");
@@ -305,3 +552,51 @@ impl fmt::Display for Board {
     }
 }
 
+
+#[test]
+fn bitboards_in_sync_test() {
+    let mut board = Board::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1").unwrap();
+    for m in movegen::legal_moves(&board).iter().take(20) {
+        let mut b = board;
+        b.make_move(m);
+        for square in 0..64 {
+            let mask = 1 << square;
+            match *b.get_square(square) {
+                Some(p) => assert_eq!(b.piece_mask(p.kind, p.color) & mask, mask),
+                None => assert_eq!(b.occupancy() & mask, 0),
+            }
+        }
+        assert_eq!(b.occupancy().count_ones(),
+                   b.color_mask(White).count_ones() + b.color_mask(Black).count_ones());
+    }
+    board.set_square(0, None);
+    assert_eq!(board.piece_mask(Rook, White) & 1, 0);
+}
+
+#[test]
+fn make_unmake_test() {
+    fn check(board: &mut Board, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        let original = *board;
+        for m in movegen::legal_moves(board) {
+            let undo = board.make_move(&m);
+            check(board, depth - 1);
+            board.unmake_move(&m, &undo);
+            assert_eq!(*board, original, "unmake {} in {}", m, original.to_fen());
+        }
+    }
+
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    ];
+    for fen in fens.iter() {
+        check(&mut Board::from_fen(fen).unwrap(), 3);
+    }
+}
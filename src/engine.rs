@@ -0,0 +1,112 @@
+// Runs searches in a background thread, so the protocol
+// front-ends can keep reading commands (`stop`, `isready`, ...)
+// while the engine is thinking
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use board::{Board, Move};
+use eval::pawns::PawnTable;
+use movepick::History;
+use search::{Search, SearchResult};
+use timeman::Limits;
+use tt::TranspositionTable;
+
+pub const DEFAULT_HASH_SIZE: usize = 16;
+
+// Size of the pawn structure cache in megabytes
+const PAWN_TABLE_SIZE: usize = 1;
+
+pub struct Engine {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    // Kept between searches, only used by one search thread at a time
+    tt: Arc<Mutex<TranspositionTable>>,
+    history: Arc<Mutex<History>>,
+    pawn_table: Arc<Mutex<PawnTable>>,
+    // Milliseconds reserved for communication with the GUI
+    pub move_overhead: u64,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: None,
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_SIZE))),
+            history: Arc::new(Mutex::new(History::new())),
+            pawn_table: Arc::new(Mutex::new(PawnTable::new(PAWN_TABLE_SIZE))),
+            move_overhead: 30,
+        }
+    }
+
+    // Size of the transposition table in megabytes, this clears it
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.stop();
+        *self.tt.lock().unwrap() = TranspositionTable::new(megabytes);
+    }
+
+    // Forget everything learned in the previous game
+    pub fn new_game(&mut self) {
+        self.stop();
+        self.tt.lock().unwrap().clear();
+        self.history.lock().unwrap().clear();
+    }
+
+    // Start thinking about `board`. Both callbacks are called from the
+    // search thread, `info` after every iteration and `done` with
+    // the move to play.
+    pub fn start<I, F>(&mut self, board: Board, limits: Limits, info: I, done: F)
+        where I: FnMut(&SearchResult, &Search) + Send + 'static,
+              F: FnOnce(Option<Move>) + Send + 'static {
+        self.stop();
+        self.stop.store(false, Ordering::SeqCst);
+
+        let stop = self.stop.clone();
+        let tt = self.tt.clone();
+        let history = self.history.clone();
+        let pawn_table = self.pawn_table.clone();
+        let overhead = self.move_overhead;
+        self.thread = Some(thread::spawn(move || {
+            let (mut tt, mut history) = (tt.lock().unwrap(), history.lock().unwrap());
+            let mut pawn_table = pawn_table.lock().unwrap();
+            let time = limits.time_for_move(board.turn, overhead);
+            let search = Search::new(&limits, time, &stop, &mut tt, &mut history, &mut pawn_table);
+            let best = think(&board, &limits, &stop, search, info);
+            done(best);
+        }));
+    }
+
+    // Interrupt the current search and wait until
+    // its result has been reported
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.wait();
+    }
+
+    pub fn wait(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn think<I>(board: &Board, limits: &Limits, stop: &AtomicBool, mut search: Search, info: I) -> Option<Move>
+    where I: FnMut(&SearchResult, &Search) {
+    let result = search.iterate(board, info);
+
+    // Infinite searches may finish early, e.g. when a mate is found,
+    // but the protocols require waiting for `stop` anyway
+    while limits.infinite && !stop.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(5));
+    }
+    result.best_move
+}
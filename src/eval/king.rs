@@ -0,0 +1,206 @@
+// King safety
+//
+// The pawns in front of a castled king shelter it, enemy pawns marching
+// towards it and open files next to it let the attack in. Pieces aiming
+// at the squares around the king add up to attack units, which are
+// turned into a penalty growing faster than linearly, since an attack
+// by several pieces is much more dangerous than each of them alone.
+// See: https://www.chessprogramming.org/King_Safety
+
+use bitscan::BitIter;
+use board::{Board, Color, Type};
+use eval::Score;
+use eval::pawns::{file_mask, relative_rank};
+use movegen::{bishop_attacks, king_attacks, knight_attacks, queen_attacks, rook_attacks};
+
+// Indexed by the relative rank of the own pawn closest to the king
+// on each file of the shelter, 0 when there is none
+const SHIELD: [Score; 8] = [
+    Score(-36, 0), Score(30, 0), Score(18, 0), Score(4, 0),
+    Score(-4, 0), Score(-8, 0), Score(-8, 0), Score(0, 0),
+];
+// Indexed by the relative rank of the closest enemy pawn, 0 when there is none
+const STORM: [Score; 8] = [
+    Score(0, 0), Score(0, 0), Score(-48, 0), Score(-32, 0),
+    Score(-16, 0), Score(-6, 0), Score(0, 0), Score(0, 0),
+];
+// A storming pawn stuck in front of an own pawn can't open a file
+const BLOCKED_STORM: [Score; 8] = [
+    Score(0, 0), Score(0, 0), Score(-10, 0), Score(-8, 0),
+    Score(-4, 0), Score(0, 0), Score(0, 0), Score(0, 0),
+];
+
+// Files next to the king without own pawns, or without any pawns
+const SEMI_OPEN_FILE: Score = Score(-14, 0);
+const OPEN_FILE: Score = Score(-28, 0);
+
+// Units per attacked square of the king zone, indexed by `Type as usize`
+const ATTACK_WEIGHTS: [usize; 6] = [0, 5, 3, 2, 0, 2];
+
+// Penalty by attack units, in centipawns
+const SAFETY_TABLE: [i32; 100] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+
+pub fn king_square(board: &Board, color: Color) -> Option<usize> {
+    match board.piece_mask(Type::King, color) {
+        0 => None,
+        mask => Some(mask.trailing_zeros() as usize),
+    }
+}
+
+// The king's file and its neighbours, shifted inwards on the edge
+fn shelter_files(king: usize) -> [u64; 3] {
+    let center = (king % 8).clamp(1, 6);
+    [file_mask(center - 1), file_mask(center), file_mask(center + 1)]
+}
+
+// Relative rank of the pawn in `pawns` closest to `color`'s side of the board
+fn closest(pawns: u64, color: Color) -> Option<usize> {
+    BitIter(pawns).map(|sq| relative_rank(sq, color)).min()
+}
+
+// Pawns level with or in front of `king`
+fn in_front(pawns: u64, king: usize, color: Color) -> u64 {
+    let rank = relative_rank(king, color);
+    BitIter(pawns).filter(|&sq| relative_rank(sq, color) >= rank).fold(0, |acc, sq| acc | 1 << sq)
+}
+
+pub fn shield(board: &Board, color: Color, king: usize) -> Score {
+    let ours = in_front(board.piece_mask(Type::Pawn, color), king, color);
+    shelter_files(king).iter().fold(Score::default(), |score, &file| {
+        score + SHIELD[closest(ours & file, color).unwrap_or(0)]
+    })
+}
+
+pub fn storm(board: &Board, color: Color, king: usize) -> Score {
+    let ours = board.piece_mask(Type::Pawn, color);
+    let theirs = in_front(board.piece_mask(Type::Pawn, color.opponent()), king, color);
+    shelter_files(king).iter().fold(Score::default(), |score, &file| {
+        let sq = match BitIter(theirs & file).min_by_key(|&sq| relative_rank(sq, color)) {
+            Some(sq) => sq,
+            None => return score,
+        };
+        let below = if color == Color::White { sq - 8 } else { sq + 8 };
+        let rank = relative_rank(sq, color);
+        score + if ours & 1 << below != 0 { BLOCKED_STORM[rank] } else { STORM[rank] }
+    })
+}
+
+pub fn open_files(board: &Board, color: Color, king: usize) -> Score {
+    let ours = board.piece_mask(Type::Pawn, color);
+    let theirs = board.piece_mask(Type::Pawn, color.opponent());
+    shelter_files(king).iter().fold(Score::default(), |score, &file| {
+        if ours & file != 0 {
+            score
+        } else if theirs & file != 0 {
+            score + SEMI_OPEN_FILE
+        } else {
+            score + OPEN_FILE
+        }
+    })
+}
+
+// Number of enemy pieces attacking the squares around `color`'s king,
+// and the attack units they add up to
+pub fn attack_units(board: &Board, color: Color, king: usize) -> (usize, usize) {
+    let zone = king_attacks(king) | 1 << king;
+    let occupancy = board.occupancy();
+    let them = color.opponent();
+
+    let mut attackers = 0;
+    let mut units = 0;
+    for &kind in [Type::Knight, Type::Bishop, Type::Rook, Type::Queen].iter() {
+        for sq in BitIter(board.piece_mask(kind, them)) {
+            let attacks = match kind {
+                Type::Knight => knight_attacks(sq),
+                Type::Bishop => bishop_attacks(sq, occupancy),
+                Type::Rook => rook_attacks(sq, occupancy),
+                _ => queen_attacks(sq, occupancy),
+            };
+            let hits = (attacks & zone).count_ones() as usize;
+            if hits > 0 {
+                attackers += 1;
+                units += ATTACK_WEIGHTS[kind as usize] * hits;
+            }
+        }
+    }
+    (attackers, units)
+}
+
+// A single piece can't mount an attack on its own
+pub fn attack(board: &Board, color: Color, king: usize) -> Score {
+    let (attackers, units) = attack_units(board, color, king);
+    if attackers < 2 {
+        return Score::default();
+    }
+    let penalty = SAFETY_TABLE[units.min(SAFETY_TABLE.len() - 1)];
+    Score(-penalty, -penalty / 4)
+}
+
+// One of the terms above, nothing when `color` has no king
+pub fn term(board: &Board, color: Color, term: fn(&Board, Color, usize) -> Score) -> Score {
+    king_square(board, color).map_or(Score::default(), |king| term(board, color, king))
+}
+
+// Safety of `color`'s king, positive when it's safe
+pub fn evaluate(board: &Board, color: Color) -> Score {
+    let king = match king_square(board, color) {
+        Some(king) => king,
+        None => return Score::default(),
+    };
+    shield(board, color, king) + storm(board, color, king) + open_files(board, color, king) +
+        attack(board, color, king)
+}
+
+#[test]
+fn shield_test() {
+    let board = Board::from_fen("6k1/5pp1/7p/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(term(&board, Color::White, shield), SHIELD[1] * 3);
+    assert_eq!(term(&board, Color::Black, shield), SHIELD[1] * 2 + SHIELD[2]);
+
+    // On the edge the shelter covers the same files as next to it,
+    // pawns behind the king don't shelter it
+    let board = Board::from_fen("8/8/8/8/7p/7k/1P6/K7 b - - 0 1").unwrap();
+    assert_eq!(term(&board, Color::White, shield), SHIELD[0] * 2 + SHIELD[1]);
+    assert_eq!(term(&board, Color::Black, shield), SHIELD[0] * 3);
+}
+
+#[test]
+fn storm_test() {
+    let board = Board::from_fen("6k1/8/8/7p/6p1/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(term(&board, Color::White, storm), STORM[3] + STORM[4]);
+    assert_eq!(term(&board, Color::Black, storm), Score::default());
+
+    let board = Board::from_fen("6k1/8/8/8/8/6p1/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(term(&board, Color::White, storm), BLOCKED_STORM[2]);
+}
+
+#[test]
+fn open_files_test() {
+    let board = Board::from_fen("6k1/7p/8/8/8/8/5P2/6K1 w - - 0 1").unwrap();
+    assert_eq!(term(&board, Color::White, open_files), OPEN_FILE + SEMI_OPEN_FILE);
+    assert_eq!(term(&board, Color::Black, open_files), OPEN_FILE + SEMI_OPEN_FILE);
+}
+
+#[test]
+fn attack_test() {
+    // The knight hits f2 and h2, the queen h2 and f2 as well
+    let board = Board::from_fen("6k1/8/8/8/6nq/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(attack_units(&board, Color::White, king_square(&board, Color::White).unwrap()), (2, 14));
+    assert_eq!(term(&board, Color::White, attack), Score(-35, -8));
+    assert_eq!(attack_units(&board, Color::Black, king_square(&board, Color::Black).unwrap()), (0, 0));
+
+    let board = Board::from_fen("6k1/8/8/8/7q/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(attack_units(&board, Color::White, king_square(&board, Color::White).unwrap()), (1, 10));
+    assert_eq!(term(&board, Color::White, attack), Score::default());
+}
@@ -0,0 +1,179 @@
+// Static evaluation
+//
+// Every term is a `Score` with separate middlegame and endgame values,
+// positive for White. The final score interpolates between the two by
+// the material left on the board, so terms can fade in or out as the
+// game progresses, and is returned from the side to move's point of view.
+// See: https://www.chessprogramming.org/Tapered_Eval
+
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use board::{Board, Color, Type};
+
+pub mod king;
+pub mod pawns;
+pub mod pieces;
+pub mod psqt;
+pub mod trace;
+
+use self::pawns::PawnTable;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score(pub i32, pub i32);
+
+impl Score {
+    pub fn mg(&self) -> i32 {
+        self.0
+    }
+
+    pub fn eg(&self) -> i32 {
+        self.1
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score(-self.0, -self.1)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, factor: i32) -> Score {
+        Score(self.0 * factor, self.1 * factor)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+// Phase of the starting position, with all pieces on the board
+pub const MAX_PHASE: i32 = 24;
+
+// Indexed by `Type as usize`, pawns and kings don't count
+const PHASE_WEIGHTS: [i32; 6] = [0, 4, 2, 1, 0, 1];
+
+// MAX_PHASE in the opening down to 0 for pawn endgames.
+// Promotions could push it above MAX_PHASE, so it's capped.
+pub fn phase(board: &Board) -> i32 {
+    let phase = [Type::Queen, Type::Rook, Type::Bishop, Type::Knight].iter()
+        .map(|&kind| board.type_mask(kind).count_ones() as i32 * PHASE_WEIGHTS[kind as usize])
+        .sum::<i32>();
+    phase.min(MAX_PHASE)
+}
+
+// Interpolate between the middlegame and endgame values
+pub fn taper(score: Score, phase: i32) -> i32 {
+    (score.mg() * phase + score.eg() * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// A single value per piece for exchanges and move ordering,
+// the material halfway between the middlegame and the endgame
+pub fn piece_value(kind: Type) -> i32 {
+    taper(psqt::MATERIAL[kind as usize], MAX_PHASE / 2)
+}
+
+// Score of the position for the side to move, in centipawns
+pub fn evaluate(board: &Board, pawn_table: &mut PawnTable) -> i32 {
+    let pawn_entry = pawn_table.probe(board);
+    let mut score = board.psqt;
+    for &(color, sign) in [(Color::White, 1), (Color::Black, -1)].iter() {
+        let pawns = pawn_entry.score[color as usize] +
+                    pawns::passed_extras(board, color, pawn_entry.passed[color as usize]);
+        score += (pawns + king::evaluate(board, color) + pieces::evaluate(board, color)) * sign;
+    }
+
+    let score = taper(score, phase(board));
+    if board.turn == Color::White { score } else { -score }
+}
+
+// The same position with colors swapped, for testing symmetry
+#[cfg(test)]
+pub fn mirror(board: &Board) -> Board {
+    use board::Piece;
+    use zobrist;
+
+    let mut mirrored = Board::empty();
+    for square in 0..64 {
+        if let Some(p) = *board.get_square(square) {
+            mirrored.set_square(square ^ 56, Some(Piece::new(p.kind, p.color.opponent())));
+        }
+    }
+    if board.turn == Color::White {
+        mirrored.turn = Color::Black;
+        mirrored.hash ^= zobrist::black_to_move();
+    }
+    mirrored
+}
+
+#[test]
+fn evaluate_test() {
+    let mut pawn_table = PawnTable::new(1);
+    let mut evaluate = |board: &Board| evaluate(board, &mut pawn_table);
+
+    let board = Board::starting_position();
+    assert_eq!(phase(&board), MAX_PHASE);
+    assert_eq!(evaluate(&board), 0);
+
+    // Without pawns, the tables plus piece activity and king safety
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+    assert_eq!(phase(&board), 2);
+    let (white, black) = (Color::White, Color::Black);
+    let expected = board.psqt + pieces::evaluate(&board, white) - pieces::evaluate(&board, black) +
+                   king::evaluate(&board, white) - king::evaluate(&board, black);
+    assert_eq!(evaluate(&board), taper(expected, 2));
+    assert!(evaluate(&board) > taper(board.psqt, 2));
+
+    // Only the endgame values count without pieces, a passed pawn scores extra
+    let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    assert_eq!(phase(&board), 0);
+    assert!(evaluate(&board) > board.psqt.eg());
+
+    let positions = [
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+    for fen in positions.iter() {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.psqt, psqt::psqt(&board));
+        assert_eq!(evaluate(&board), evaluate(&mirror(&board)), "{}", fen);
+    }
+}
+
+#[test]
+fn taper_test() {
+    let score = Score(100, -50);
+    assert_eq!(taper(score, MAX_PHASE), 100);
+    assert_eq!(taper(score, 0), -50);
+    assert_eq!(taper(score, MAX_PHASE / 2), 25);
+    assert_eq!(Score(3, 4) * 2 - Score(1, 1) + -Score(1, 0), Score(4, 7));
+}
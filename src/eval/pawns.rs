@@ -0,0 +1,269 @@
+// Pawn structure
+//
+// Doubled, isolated, backward, connected and passed pawns only depend
+// on the pawns themselves, so their evaluation is cached in a hash table
+// keyed by `Board::pawn_hash`. Passed pawns are looked at once more on
+// every evaluation, as how far they get also depends on the other pieces
+// and the kings.
+// See: https://www.chessprogramming.org/Pawn_Structure
+
+use std::mem;
+
+use bitscan::BitIter;
+use board::{Board, Color, Type};
+use eval::Score;
+use helper;
+use movegen::pawn_attacks;
+
+const DOUBLED: Score = Score(-11, -51);
+const ISOLATED: Score = Score(-5, -15);
+const BACKWARD: Score = Score(-9, -24);
+
+// Indexed by the rank relative to the pawn's color
+const CONNECTED: [Score; 8] = [
+    Score(0, 0), Score(7, 0), Score(8, 4), Score(12, 8),
+    Score(29, 22), Score(48, 40), Score(86, 80), Score(0, 0),
+];
+const PASSED: [Score; 8] = [
+    Score(0, 0), Score(10, 28), Score(17, 33), Score(15, 41),
+    Score(62, 72), Score(168, 177), Score(276, 260), Score(0, 0),
+];
+// No piece of either color stands between a passed pawn and its promotion square
+const PASSED_FREE_PATH: [Score; 8] = [
+    Score(0, 0), Score(0, 0), Score(0, 5), Score(5, 10),
+    Score(15, 25), Score(30, 50), Score(50, 90), Score(0, 0),
+];
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+pub fn file_mask(square: usize) -> u64 {
+    FILE_A << (square % 8)
+}
+
+pub fn adjacent_files(square: usize) -> u64 {
+    let file = file_mask(square);
+    ((file << 1) & !FILE_A) | ((file >> 1) & !(FILE_A << 7))
+}
+
+// All squares on ranks in front of `square`, as seen by `color`
+pub fn forward_ranks(square: usize, color: Color) -> u64 {
+    let rank = square / 8;
+    match color {
+        Color::White if rank == 7 => 0,
+        Color::White => !0 << (8 * (rank + 1)),
+        Color::Black => (1 << (8 * rank)) - 1,
+    }
+}
+
+// Squares in front of a pawn on its own file
+fn front_span(square: usize, color: Color) -> u64 {
+    forward_ranks(square, color) & file_mask(square)
+}
+
+// Squares in front of a pawn on its own and the adjacent files,
+// a pawn is passed when there are no enemy pawns on them
+fn passed_span(square: usize, color: Color) -> u64 {
+    forward_ranks(square, color) & (file_mask(square) | adjacent_files(square))
+}
+
+pub fn relative_rank(square: usize, color: Color) -> usize {
+    match color {
+        Color::White => square / 8,
+        Color::Black => 7 - square / 8,
+    }
+}
+
+// Number of king moves between two squares
+pub fn distance(a: usize, b: usize) -> i32 {
+    let files = (a % 8) as i32 - (b % 8) as i32;
+    let ranks = (a / 8) as i32 - (b / 8) as i32;
+    files.abs().max(ranks.abs())
+}
+
+fn pawns(board: &Board, color: Color) -> (u64, u64) {
+    (board.piece_mask(Type::Pawn, color), board.piece_mask(Type::Pawn, color.opponent()))
+}
+
+// Pawns with another pawn of the same color in front of them
+pub fn doubled(board: &Board, color: Color) -> u64 {
+    let (ours, _) = pawns(board, color);
+    BitIter(ours).filter(|&sq| front_span(sq, color) & ours != 0).fold(0, |acc, sq| acc | 1 << sq)
+}
+
+// Pawns without friendly pawns on the adjacent files
+pub fn isolated(board: &Board, color: Color) -> u64 {
+    let (ours, _) = pawns(board, color);
+    BitIter(ours).filter(|&sq| adjacent_files(sq) & ours == 0).fold(0, |acc, sq| acc | 1 << sq)
+}
+
+// Pawns that have fallen behind their neighbours, so no pawn
+// can come to support them, and can't safely advance either
+pub fn backward(board: &Board, color: Color) -> u64 {
+    let (ours, theirs) = pawns(board, color);
+    BitIter(ours & !isolated(board, color)).filter(|&sq| {
+        let behind = adjacent_files(sq) & !forward_ranks(sq, color);
+        let stop = if color == Color::White { sq + 8 } else { sq - 8 };
+        behind & ours == 0 && pawn_attacks(stop, color) & theirs != 0
+    }).fold(0, |acc, sq| acc | 1 << sq)
+}
+
+// Pawns defended by a friendly pawn or standing next to one
+pub fn connected(board: &Board, color: Color) -> u64 {
+    let (ours, _) = pawns(board, color);
+    BitIter(ours).filter(|&sq| {
+        let supported = pawn_attacks(sq, color.opponent()) & ours != 0;
+        let phalanx = adjacent_files(sq) & (0xff << (sq / 8 * 8)) & ours != 0;
+        supported || phalanx
+    }).fold(0, |acc, sq| acc | 1 << sq)
+}
+
+// Pawns no enemy pawn can stop, only the frontmost of doubled pawns counts
+pub fn passed(board: &Board, color: Color) -> u64 {
+    let (ours, theirs) = pawns(board, color);
+    BitIter(ours)
+        .filter(|&sq| passed_span(sq, color) & theirs == 0 && front_span(sq, color) & ours == 0)
+        .fold(0, |acc, sq| acc | 1 << sq)
+}
+
+// Structure score of one color's pawns
+fn structure(board: &Board, color: Color) -> Score {
+    let mut score = DOUBLED * doubled(board, color).count_ones() as i32 +
+                    ISOLATED * isolated(board, color).count_ones() as i32 +
+                    BACKWARD * backward(board, color).count_ones() as i32;
+    for sq in BitIter(connected(board, color)) {
+        score += CONNECTED[relative_rank(sq, color)];
+    }
+    for sq in BitIter(passed(board, color)) {
+        score += PASSED[relative_rank(sq, color)];
+    }
+    score
+}
+
+// Passed pawn terms that depend on more than the pawns: a free path to
+// the promotion square, and in the endgame the distance of both kings
+// to the square in front of the pawn
+pub fn passed_extras(board: &Board, color: Color, passed: u64) -> Score {
+    let occupancy = board.occupancy();
+    let king = |c: Color| board.piece_mask(Type::King, c).trailing_zeros() as usize;
+
+    BitIter(passed).fold(Score::default(), |mut score, sq| {
+        let rank = relative_rank(sq, color);
+        if front_span(sq, color) & occupancy == 0 {
+            score += PASSED_FREE_PATH[rank];
+        }
+
+        let stop = if color == Color::White { sq + 8 } else { sq - 8 };
+        if rank > 2 && board.piece_mask(Type::King, Color::White) != 0 &&
+           board.piece_mask(Type::King, Color::Black) != 0 {
+            let weight = rank as i32 - 2;
+            let theirs = distance(king(color.opponent()), stop);
+            let ours = distance(king(color), stop);
+            score += Score(0, weight * (5 * theirs - 2 * ours));
+        }
+        score
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PawnEntry {
+    key: u64,
+    // Indexed by `Color as usize`
+    pub score: [Score; 2],
+    pub passed: [u64; 2],
+}
+
+pub fn evaluate(board: &Board) -> PawnEntry {
+    PawnEntry {
+        key: board.pawn_hash,
+        score: [structure(board, Color::Black), structure(board, Color::White)],
+        passed: [passed(board, Color::Black), passed(board, Color::White)],
+    }
+}
+
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+    mask: usize,
+}
+
+impl PawnTable {
+    pub fn new(megabytes: usize) -> Self {
+        let size = helper::table_len(megabytes, mem::size_of::<PawnEntry>());
+
+        PawnTable {
+            entries: vec![PawnEntry::default(); size],
+            mask: size - 1,
+        }
+    }
+
+    // Always replace, pawn structures change rarely
+    // so the table is hit most of the time anyway.
+    // Boards without pawns have a key of 0, just like empty entries,
+    // which hold the right (empty) evaluation for them.
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let entry = &mut self.entries[board.pawn_hash as usize & self.mask];
+        if entry.key != board.pawn_hash {
+            *entry = evaluate(board);
+        }
+        *entry
+    }
+}
+
+#[cfg(test)]
+fn squares(names: &[&str]) -> u64 {
+    use board::Position;
+    names.iter().fold(0, |acc, name| acc | Position::parse(name).unwrap().mask())
+}
+
+#[test]
+fn pawn_structure_test() {
+    let board = Board::from_fen("4k3/p7/3p4/4P2P/2P2P2/2P5/P7/4K3 w - - 0 1").unwrap();
+    let white = Color::White;
+    let black = Color::Black;
+
+    assert_eq!(doubled(&board, white), squares(&["c3"]));
+    assert_eq!(doubled(&board, black), 0);
+    assert_eq!(isolated(&board, white), squares(&["a2", "c3", "c4", "h5"]));
+    assert_eq!(isolated(&board, black), squares(&["a7", "d6"]));
+    assert_eq!(connected(&board, white), squares(&["e5"]));
+    assert_eq!(passed(&board, white), squares(&["f4", "h5"]));
+    assert_eq!(passed(&board, black), 0);
+
+    // d3 can't be supported and c5 guards the square in front of it
+    let board = Board::from_fen("4k3/8/8/2p5/2P1P3/3P4/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(backward(&board, white), squares(&["d3"]));
+    assert_eq!(connected(&board, white), squares(&["c4", "e4"]));
+    assert_eq!(backward(&board, black), 0);
+
+    // Phalanx and the frontmost of doubled passed pawns
+    let board = Board::from_fen("4k3/8/3pp3/8/8/1P6/1P6/4K3 b - - 0 1").unwrap();
+    assert_eq!(connected(&board, black), squares(&["d6", "e6"]));
+    assert_eq!(passed(&board, white), squares(&["b3"]));
+    assert_eq!(passed(&board, black), squares(&["d6", "e6"]));
+    assert_eq!(structure(&board, white), DOUBLED + ISOLATED * 2 + PASSED[2]);
+}
+
+#[test]
+fn passed_extras_test() {
+    // Both kings far away, the path to a8 is free
+    let board = Board::from_fen("7k/8/P7/8/8/8/8/7K w - - 0 1").unwrap();
+    let passers = passed(&board, Color::White);
+    assert_eq!(passed_extras(&board, Color::White, passers), PASSED_FREE_PATH[5] + Score(0, 3 * (5 * 7 - 2 * 7)));
+
+    // A blocked passer, with the enemy king in front of it
+    let board = Board::from_fen("k7/8/P7/8/8/8/8/7K w - - 0 1").unwrap();
+    let passers = passed(&board, Color::White);
+    assert_eq!(passed_extras(&board, Color::White, passers), Score(0, 3 * (5 - 2 * 7)));
+}
+
+#[test]
+fn pawn_table_test() {
+    let mut table = PawnTable::new(1);
+    let a = Board::from_fen("4k3/p7/3p4/4P2P/2P2P2/2P5/P7/4K3 w - - 0 1").unwrap();
+    let b = Board::from_fen("r3k3/p7/3p4/4P2P/2P2P2/2PQ4/P7/2B1K3 b - - 0 1").unwrap();
+    assert_eq!(a.pawn_hash, b.pawn_hash);
+    assert_eq!(table.probe(&a), evaluate(&a));
+    assert_eq!(table.probe(&b), evaluate(&a));
+
+    let empty = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(table.probe(&empty), PawnEntry::default());
+}
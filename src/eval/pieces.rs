@@ -0,0 +1,237 @@
+// Piece activity
+//
+// How much the pieces can do from where they stand: the squares they
+// reach, outposts the enemy pawns can't chase them away from, rooks on
+// open files or the 7th rank, and pieces walled in by pawns. Each term
+// has its own function so they can be looked at one by one.
+// See: https://www.chessprogramming.org/Evaluation_of_Pieces
+
+use bitscan::BitIter;
+use board::{Board, Color, Type};
+use eval::Score;
+use eval::pawns::{adjacent_files, file_mask, forward_ranks, relative_rank};
+use movegen::{bishop_attacks, knight_attacks, pawn_attacks, queen_attacks, rook_attacks};
+
+// Indexed by the number of squares a piece reaches
+const KNIGHT_MOBILITY: [Score; 9] = [
+    Score(-62, -81), Score(-53, -56), Score(-12, -31), Score(-4, -16), Score(3, 5),
+    Score(13, 11), Score(22, 17), Score(28, 20), Score(33, 25),
+];
+const BISHOP_MOBILITY: [Score; 14] = [
+    Score(-48, -59), Score(-20, -23), Score(16, -3), Score(26, 13), Score(38, 24),
+    Score(51, 42), Score(55, 54), Score(63, 57), Score(63, 65), Score(68, 73),
+    Score(81, 78), Score(81, 86), Score(91, 88), Score(98, 97),
+];
+const ROOK_MOBILITY: [Score; 15] = [
+    Score(-60, -78), Score(-20, -17), Score(2, 23), Score(3, 39), Score(3, 70),
+    Score(11, 99), Score(22, 103), Score(31, 121), Score(40, 134), Score(40, 139),
+    Score(41, 158), Score(48, 164), Score(57, 168), Score(57, 169), Score(62, 172),
+];
+const QUEEN_MOBILITY: [Score; 28] = [
+    Score(-30, -48), Score(-12, -30), Score(-8, -7), Score(-9, 19), Score(20, 40),
+    Score(23, 55), Score(23, 59), Score(35, 75), Score(38, 78), Score(53, 96),
+    Score(64, 96), Score(65, 100), Score(65, 121), Score(66, 127), Score(67, 131),
+    Score(67, 133), Score(72, 136), Score(72, 141), Score(77, 147), Score(79, 150),
+    Score(93, 151), Score(108, 168), Score(108, 168), Score(108, 171), Score(110, 182),
+    Score(114, 182), Score(114, 192), Score(116, 219),
+];
+
+// A minor piece on the 4th to 6th rank, defended by a pawn,
+// where no enemy pawn can ever attack it
+const KNIGHT_OUTPOST: Score = Score(36, 12);
+const BISHOP_OUTPOST: Score = Score(18, 6);
+
+const BISHOP_PAIR: Score = Score(30, 60);
+
+const ROOK_OPEN_FILE: Score = Score(45, 20);
+const ROOK_SEMI_OPEN_FILE: Score = Score(20, 7);
+// Only when there are enemy pawns to eat or the enemy king is stuck on the last rank
+const ROOK_ON_SEVENTH: Score = Score(20, 40);
+
+// A rook shut in on the back rank by its own king, which can't castle any more
+const TRAPPED_ROOK: Score = Score(-50, -10);
+// A bishop that took a rook pawn and is cut off by the pawn next to it (Bxa7 b6)
+const TRAPPED_BISHOP: Score = Score(-100, -100);
+
+// Squares attacked by `color`'s pawns
+fn pawn_attacks_by(board: &Board, color: Color) -> u64 {
+    BitIter(board.piece_mask(Type::Pawn, color)).fold(0, |acc, sq| acc | pawn_attacks(sq, color))
+}
+
+fn attacks(kind: Type, square: usize, occupancy: u64) -> u64 {
+    match kind {
+        Type::Knight => knight_attacks(square),
+        Type::Bishop => bishop_attacks(square, occupancy),
+        Type::Rook => rook_attacks(square, occupancy),
+        Type::Queen => queen_attacks(square, occupancy),
+        _ => 0,
+    }
+}
+
+// Squares that count for mobility, leaving out
+// those taken by own pieces or attacked by enemy pawns
+fn mobility_area(board: &Board, color: Color) -> u64 {
+    !board.color_mask(color) & !pawn_attacks_by(board, color.opponent())
+}
+
+fn reach(board: &Board, kind: Type, square: usize, area: u64) -> usize {
+    (attacks(kind, square, board.occupancy()) & area).count_ones() as usize
+}
+
+pub fn mobility(board: &Board, color: Color) -> Score {
+    let tables: [(Type, &[Score]); 4] = [
+        (Type::Knight, &KNIGHT_MOBILITY),
+        (Type::Bishop, &BISHOP_MOBILITY),
+        (Type::Rook, &ROOK_MOBILITY),
+        (Type::Queen, &QUEEN_MOBILITY),
+    ];
+    let area = mobility_area(board, color);
+    let mut score = Score::default();
+    for &(kind, table) in tables.iter() {
+        for sq in BitIter(board.piece_mask(kind, color)) {
+            score += table[reach(board, kind, sq, area)];
+        }
+    }
+    score
+}
+
+pub fn outposts(board: &Board, color: Color) -> Score {
+    let ours = board.piece_mask(Type::Pawn, color);
+    let theirs = board.piece_mask(Type::Pawn, color.opponent());
+    let is_outpost = |sq: usize| {
+        let rank = relative_rank(sq, color);
+        (3..=5).contains(&rank) &&
+            pawn_attacks(sq, color.opponent()) & ours != 0 &&
+            forward_ranks(sq, color) & adjacent_files(sq) & theirs == 0
+    };
+
+    let knights = BitIter(board.piece_mask(Type::Knight, color)).filter(|&sq| is_outpost(sq)).count();
+    let bishops = BitIter(board.piece_mask(Type::Bishop, color)).filter(|&sq| is_outpost(sq)).count();
+    KNIGHT_OUTPOST * knights as i32 + BISHOP_OUTPOST * bishops as i32
+}
+
+pub fn bishop_pair(board: &Board, color: Color) -> Score {
+    if board.piece_mask(Type::Bishop, color).count_ones() >= 2 { BISHOP_PAIR } else { Score::default() }
+}
+
+pub fn rook_files(board: &Board, color: Color) -> Score {
+    let ours = board.piece_mask(Type::Pawn, color);
+    let pawns = board.type_mask(Type::Pawn);
+    BitIter(board.piece_mask(Type::Rook, color)).fold(Score::default(), |score, sq| {
+        let file = file_mask(sq);
+        if pawns & file == 0 {
+            score + ROOK_OPEN_FILE
+        } else if ours & file == 0 {
+            score + ROOK_SEMI_OPEN_FILE
+        } else {
+            score
+        }
+    })
+}
+
+pub fn rook_on_seventh(board: &Board, color: Color) -> Score {
+    let them = color.opponent();
+    let on_rank = |mask: u64, rank: usize| BitIter(mask).any(|sq| relative_rank(sq, color) == rank);
+    if !on_rank(board.piece_mask(Type::Pawn, them), 6) && !on_rank(board.piece_mask(Type::King, them), 7) {
+        return Score::default();
+    }
+    let rooks = BitIter(board.piece_mask(Type::Rook, color)).filter(|&sq| relative_rank(sq, color) == 6).count();
+    ROOK_ON_SEVENTH * rooks as i32
+}
+
+pub fn trapped(board: &Board, color: Color) -> Score {
+    // Squares as seen from White's side, so a1 is the own corner
+    let relative = |sq: usize| if color == Color::White { sq } else { sq ^ 56 };
+    let mut score = Score::default();
+
+    let king = board.piece_mask(Type::King, color);
+    if king != 0 && relative_rank(king.trailing_zeros() as usize, color) == 0 {
+        let king_file = relative(king.trailing_zeros() as usize) % 8;
+        for sq in BitIter(board.piece_mask(Type::Rook, color)) {
+            let file = relative(sq) % 8;
+            let shut_in = (king_file >= 5 && file > king_file) || (king_file <= 2 && file < king_file);
+            if relative_rank(sq, color) == 0 && shut_in && reach(board, Type::Rook, sq, mobility_area(board, color)) <= 3 {
+                score += TRAPPED_ROOK;
+            }
+        }
+    }
+
+    // Bishops on a7 or h7, with the enemy pawn on b6 or g6
+    let their_pawns = board.piece_mask(Type::Pawn, color.opponent());
+    for sq in BitIter(board.piece_mask(Type::Bishop, color)) {
+        let blocker = match relative(sq) {
+            48 => 41,
+            55 => 46,
+            _ => continue,
+        };
+        if their_pawns & 1 << relative(blocker) != 0 {
+            score += TRAPPED_BISHOP;
+        }
+    }
+    score
+}
+
+// Activity of `color`'s pieces
+pub fn evaluate(board: &Board, color: Color) -> Score {
+    mobility(board, color) + outposts(board, color) + bishop_pair(board, color) +
+        rook_files(board, color) + rook_on_seventh(board, color) + trapped(board, color)
+}
+
+#[test]
+fn mobility_test() {
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+    assert_eq!(mobility(&board, Color::White), KNIGHT_MOBILITY[2]);
+    assert_eq!(mobility(&board, Color::Black), Score::default());
+
+    // b3 is attacked by the pawn, e1 taken by the king
+    let board = Board::from_fen("4k3/8/8/8/2p5/8/8/N3K2R w - - 0 1").unwrap();
+    assert_eq!(mobility(&board, Color::White), KNIGHT_MOBILITY[1] + ROOK_MOBILITY[9]);
+
+    let board = Board::from_fen("4k3/8/8/8/3q4/8/8/4K3 b - - 0 1").unwrap();
+    assert_eq!(mobility(&board, Color::Black), QUEEN_MOBILITY[27]);
+}
+
+#[test]
+fn outposts_test() {
+    let board = Board::from_fen("4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(outposts(&board, Color::White), KNIGHT_OUTPOST);
+
+    // The c-pawn can still chase the knight away
+    let board = Board::from_fen("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(outposts(&board, Color::White), Score::default());
+
+    let board = Board::from_fen("4k3/8/8/3p4/2b5/8/8/4K3 b - - 0 1").unwrap();
+    assert_eq!(outposts(&board, Color::Black), BISHOP_OUTPOST);
+}
+
+#[test]
+fn rooks_test() {
+    let board = Board::from_fen("6k1/p4ppp/8/8/8/8/5PPP/R2R2K1 w - - 0 1").unwrap();
+    assert_eq!(rook_files(&board, Color::White), ROOK_OPEN_FILE + ROOK_SEMI_OPEN_FILE);
+    assert_eq!(rook_on_seventh(&board, Color::White), Score::default());
+
+    let board = Board::from_fen("6k1/1R6/8/8/8/8/8/6K1 w - - 0 1").unwrap();
+    assert_eq!(rook_on_seventh(&board, Color::White), ROOK_ON_SEVENTH);
+    let board = Board::from_fen("8/1R6/6k1/8/8/8/8/6K1 w - - 0 1").unwrap();
+    assert_eq!(rook_on_seventh(&board, Color::White), Score::default());
+}
+
+#[test]
+fn bishop_pair_test() {
+    let board = Board::from_fen("4kb2/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+    assert_eq!(bishop_pair(&board, Color::White), BISHOP_PAIR);
+    assert_eq!(bishop_pair(&board, Color::Black), Score::default());
+}
+
+#[test]
+fn trapped_test() {
+    let board = Board::from_fen("6k1/8/8/8/8/8/5PPP/5KR1 w - - 0 1").unwrap();
+    assert_eq!(trapped(&board, Color::White), TRAPPED_ROOK);
+    let board = Board::from_fen("6k1/8/8/8/8/8/5PPP/4K1R1 w - - 0 1").unwrap();
+    assert_eq!(trapped(&board, Color::White), Score::default());
+
+    let board = Board::from_fen("6k1/B7/1p6/8/8/8/8/6K1 w - - 0 1").unwrap();
+    assert_eq!(trapped(&board, Color::White), TRAPPED_BISHOP);
+    let board = Board::from_fen("6k1/8/8/8/8/6P1/7b/6K1 b - - 0 1").unwrap();
+    assert_eq!(trapped(&board, Color::Black), TRAPPED_BISHOP);
+}
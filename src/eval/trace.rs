@@ -0,0 +1,119 @@
+// Breakdown of the static evaluation
+//
+// Every term of `evaluate`, computed from scratch for both colors,
+// with the middlegame and endgame values shown side by side. Meant
+// for finding out why the engine likes or dislikes a position.
+
+use board::{Board, Color};
+use eval::{self, king, pawns, pieces, psqt, Score};
+
+type Term = fn(&Board, Color) -> Score;
+
+fn material(board: &Board, color: Color) -> Score {
+    (0..64).filter_map(|sq| *board.get_square(sq))
+        .filter(|p| p.color == color)
+        .fold(Score::default(), |acc, p| acc + psqt::MATERIAL[p.kind as usize])
+}
+
+// The tables without the material, `piece_square` has both
+fn tables(board: &Board, color: Color) -> Score {
+    let sign = if color == Color::White { 1 } else { -1 };
+    let total = (0..64).fold(Score::default(), |acc, sq| match *board.get_square(sq) {
+        Some(piece) if piece.color == color => acc + psqt::piece_square(piece, sq) * sign,
+        _ => acc,
+    });
+    total - material(board, color)
+}
+
+fn pawn_structure(board: &Board, color: Color) -> Score {
+    pawns::evaluate(board).score[color as usize]
+}
+
+fn passed_pawns(board: &Board, color: Color) -> Score {
+    pawns::passed_extras(board, color, pawns::passed(board, color))
+}
+
+const TERMS: [(&str, Term); 14] = [
+    ("Material", material),
+    ("Psqt", tables),
+    ("Pawns", pawn_structure),
+    ("Passed", passed_pawns),
+    ("King shield", |board, color| king::term(board, color, king::shield)),
+    ("King storm", |board, color| king::term(board, color, king::storm)),
+    ("King files", |board, color| king::term(board, color, king::open_files)),
+    ("King attack", |board, color| king::term(board, color, king::attack)),
+    ("Mobility", pieces::mobility),
+    ("Outposts", pieces::outposts),
+    ("Bishop pair", pieces::bishop_pair),
+    ("Rook files", pieces::rook_files),
+    ("Rook on 7th", pieces::rook_on_seventh),
+    ("Trapped", pieces::trapped),
+];
+
+// Each term for Black and White, indexed by `Color as usize`
+pub fn terms(board: &Board) -> Vec<(&'static str, [Score; 2])> {
+    TERMS.iter().map(|&(name, term)| (name, [term(board, Color::Black), term(board, Color::White)])).collect()
+}
+
+fn pawns(centipawns: i32) -> String {
+    format!("{:6.2}", centipawns as f64 / 100.0)
+}
+
+fn row(name: &str, white: Score, black: Score) -> String {
+    let total = white - black;
+    format!("{:>12} | {} {} | {} {} | {} {}\n", name,
+            pawns(white.mg()), pawns(white.eg()),
+            pawns(black.mg()), pawns(black.eg()),
+            pawns(total.mg()), pawns(total.eg()))
+}
+
+// A table of all terms in pawns, in the style of Stockfish's `eval`
+pub fn trace_eval(board: &Board) -> String {
+    let mut res = String::new();
+    res += "        Term |     White     |     Black     |     Total\n";
+    res += "             |     MG     EG |     MG     EG |     MG     EG\n";
+    res += " ------------+---------------+---------------+--------------\n";
+
+    let mut white = Score::default();
+    let mut black = Score::default();
+    for (name, scores) in terms(board) {
+        res += &row(name, scores[Color::White as usize], scores[Color::Black as usize]);
+        white += scores[Color::White as usize];
+        black += scores[Color::Black as usize];
+    }
+    res += " ------------+---------------+---------------+--------------\n";
+    res += &row("Total", white, black);
+
+    let phase = eval::phase(board);
+    let score = eval::taper(white - black, phase);
+    res += &format!("\nPhase: {}/{}\n", phase, eval::MAX_PHASE);
+    res += &format!("Final evaluation: {:+.2} (white side)", score as f64 / 100.0);
+    res
+}
+
+#[test]
+fn trace_test() {
+    use eval::pawns::PawnTable;
+
+    let mut pawn_table = PawnTable::new(1);
+    let positions = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        "6k1/B7/1p6/8/8/8/5PPP/5KR1 b - - 0 1",
+    ];
+    for fen in positions.iter() {
+        let board = Board::from_fen(fen).unwrap();
+        let total = terms(&board).iter().fold(Score::default(), |acc, &(_, s)| {
+            acc + s[Color::White as usize] - s[Color::Black as usize]
+        });
+        let score = eval::taper(total, eval::phase(&board));
+        let expected = eval::evaluate(&board, &mut pawn_table);
+        assert_eq!(if board.turn == Color::White { score } else { -score }, expected, "{}", fen);
+    }
+
+    let trace = trace_eval(&Board::starting_position());
+    assert!(trace.contains("    Material |  40.39  38.68 |  40.39  38.68 |   0.00   0.00\n"));
+    assert!(trace.contains("Phase: 24/24\n"));
+    assert!(trace.ends_with("Final evaluation: +0.00 (white side)"));
+}
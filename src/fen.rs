@@ -0,0 +1,305 @@
+use std::fmt;
+
+use board::{Board, Color, Piece, Position, Type};
+use zobrist;
+use board::{CASTLE_WHITE_KING, CASTLE_WHITE_QUEEN, CASTLE_BLACK_KING, CASTLE_BLACK_QUEEN};
+
+// Forsyth-Edwards Notation, e.g.
+//   rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1
+//
+// Fields: piece placement (rank 8 first), side to move, castling rights,
+// en passant target square, halfmove clock and fullmove number.
+// The last two fields are optional and default to "0 1".
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    WrongRankCount(usize),
+    // Rank in chess notation (1 - 8)
+    BadRankLength(i64),
+    InvalidPiece(char),
+    TooManyKings(Color),
+    MissingKing(Color),
+    PawnOnBackRank(Position),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    BadEnPassantSquare(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FenError::WrongFieldCount(n) => write!(f, "expected 4 to 6 fields, found {}", n),
+            FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            FenError::BadRankLength(rank) => write!(f, "rank {} does not have 8 squares", rank),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::TooManyKings(color) => write!(f, "{:?} has more than one king", color),
+            FenError::MissingKing(color) => write!(f, "{:?} has no king", color),
+            FenError::PawnOnBackRank(pos) => write!(f, "pawn on back rank at {}", pos),
+            FenError::InvalidSideToMove(ref s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(ref s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::BadEnPassantSquare(ref s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(ref s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(ref s) => write!(f, "invalid fullmove number '{}'", s),
+        }
+    }
+}
+
+fn parse_placement(board: &mut Board, placement: &str) -> Result<(), FenError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+
+    for (row, rank_str) in ranks.iter().enumerate() {
+        let rank = 7 - row as i64;
+        let mut file = 0;
+
+        for c in rank_str.chars() {
+            match c {
+                '1'..='8' => {
+                    file += c.to_digit(10).unwrap() as i64;
+                }
+                other => {
+                    let piece = Piece::from_fen(other).ok_or(FenError::InvalidPiece(other))?;
+                    if file < 8 {
+                        board.set_pos(&Position::new(file, rank), Some(piece));
+                    }
+                    file += 1;
+                }
+            }
+
+            if file > 8 {
+                return Err(FenError::BadRankLength(rank + 1));
+            }
+        }
+
+        if file != 8 {
+            return Err(FenError::BadRankLength(rank + 1));
+        }
+    }
+
+    for &color in [Color::White, Color::Black].iter() {
+        let king = Some(Piece::new(Type::King, color));
+        let kings = (0..64).filter(|&i| *board.get(i % 8, i / 8) == king).count();
+        if kings == 0 {
+            return Err(FenError::MissingKing(color));
+        } else if kings > 1 {
+            return Err(FenError::TooManyKings(color));
+        }
+    }
+
+    for &rank in [0, 7].iter() {
+        for file in 0..8 {
+            let pos = Position::new(file, rank);
+            if let Some(piece) = *board.get_pos(&pos) {
+                if piece.kind == Type::Pawn {
+                    return Err(FenError::PawnOnBackRank(pos));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_castling(board: &mut Board, field: &str) -> Result<(), FenError> {
+    if field == "-" {
+        return Ok(());
+    }
+
+    let error = || FenError::InvalidCastling(field.to_string());
+
+    for c in field.chars() {
+        let (right, color, rank, rook_file) = match c {
+            'K' => (CASTLE_WHITE_KING, Color::White, 0, 7),
+            'Q' => (CASTLE_WHITE_QUEEN, Color::White, 0, 0),
+            'k' => (CASTLE_BLACK_KING, Color::Black, 7, 7),
+            'q' => (CASTLE_BLACK_QUEEN, Color::Black, 7, 0),
+            _ => return Err(error()),
+        };
+
+        // Rights are only valid if king and rook are still on their
+        // initial squares, each letter may only appear once
+        let king = *board.get_pos(&Position::new(4, rank));
+        let rook = *board.get_pos(&Position::new(rook_file, rank));
+        if board.can_castle(right) ||
+           king != Some(Piece::new(Type::King, color)) ||
+           rook != Some(Piece::new(Type::Rook, color)) {
+            return Err(error());
+        }
+
+        board.castling |= right;
+    }
+
+    Ok(())
+}
+
+fn parse_en_passant(board: &mut Board, field: &str) -> Result<(), FenError> {
+    if field == "-" {
+        return Ok(());
+    }
+
+    let error = || FenError::BadEnPassantSquare(field.to_string());
+    let square = Position::parse(field).ok_or_else(error)?;
+
+    // The pawn that just moved two squares must be in front of the
+    // target square, the target and the starting square must be empty
+    let (rank, forward) = if board.turn == Color::White { (5, -1) } else { (2, 1) };
+    let pawn = Some(Piece::new(Type::Pawn, board.turn.opponent()));
+    if square.rank != rank ||
+       *board.get_pos(&Position::new(square.file, rank + forward)) != pawn ||
+       board.get_pos(&square).is_some() ||
+       board.get_pos(&Position::new(square.file, rank - forward)).is_some() {
+        return Err(error());
+    }
+
+    // Like make_move, drop the square if no pawn can legally capture
+    // there so that equal positions always have equal FENs
+    if board.en_passant_capturable(&Position::new(square.file, rank + forward), &square) {
+        board.en_passant = Some(square);
+    }
+
+    Ok(())
+}
+
+impl Board {
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let mut board = Board::empty();
+        parse_placement(&mut board, fields[0])?;
+
+        board.turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => return Err(FenError::InvalidSideToMove(other.to_string())),
+        };
+
+        parse_castling(&mut board, fields[2])?;
+        parse_en_passant(&mut board, fields[3])?;
+
+        if let Some(field) = fields.get(4) {
+            board.halfmove_clock = field.parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(field.to_string()))?;
+        }
+
+        if let Some(field) = fields.get(5) {
+            board.fullmove_number = match field.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(FenError::InvalidFullmoveNumber(field.to_string())),
+            };
+        }
+
+        board.hash = zobrist::hash(&board);
+        Ok(board)
+    }
+
+    // Board is Copy, but far too large to pass around by value
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_fen(&self) -> String {
+        let mut res = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match *self.get(file, rank) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            res += &empty.to_string();
+                            empty = 0;
+                        }
+                        res += &piece.to_string();
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                res += &empty.to_string();
+            }
+            if rank > 0 {
+                res.push('/');
+            }
+        }
+
+        res.push_str(if self.turn == Color::White { " w " } else { " b " });
+
+        if self.castling == 0 {
+            res.push('-');
+        } else {
+            let letters = [
+                (CASTLE_WHITE_KING, 'K'),
+                (CASTLE_WHITE_QUEEN, 'Q'),
+                (CASTLE_BLACK_KING, 'k'),
+                (CASTLE_BLACK_QUEEN, 'q'),
+            ];
+            for &(right, letter) in letters.iter() {
+                if self.can_castle(right) {
+                    res.push(letter);
+                }
+            }
+        }
+
+        match self.en_passant {
+            Some(square) => res += &format!(" {}", square.to_string().to_lowercase()),
+            None => res.push_str(" -"),
+        }
+
+        res += &format!(" {} {}", self.halfmove_clock, self.fullmove_number);
+        res
+    }
+}
+
+#[test]
+fn fen_round_trip_test() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+
+    for fen in fens.iter() {
+        assert_eq!(Board::from_fen(fen).unwrap().to_fen(), *fen);
+    }
+
+    assert_eq!(Board::starting_position().to_fen(), fens[0]);
+    assert_eq!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap().to_fen(),
+               "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    assert_eq!(Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+                   .unwrap().to_fen(),
+               "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+}
+
+#[test]
+fn fen_error_test() {
+    let err = |fen: &str| Board::from_fen(fen).err().unwrap();
+
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3"), FenError::WrongFieldCount(1));
+    assert_eq!(err("4k3/8/8/8/8/8/4K3 w - - 0 1"), FenError::WrongRankCount(7));
+    assert_eq!(err("4k3/8/8/8/8/8/9/4K3 w - - 0 1"), FenError::InvalidPiece('9'));
+    assert_eq!(err("4k3/8/8/8/8/8/7/4K3 w - - 0 1"), FenError::BadRankLength(2));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3p w - - 0 1"), FenError::BadRankLength(1));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K2x w - - 0 1"), FenError::InvalidPiece('x'));
+    assert_eq!(err("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"), FenError::TooManyKings(Color::White));
+    assert_eq!(err("8/8/8/8/8/8/8/4K3 w - - 0 1"), FenError::MissingKing(Color::Black));
+    assert_eq!(err("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+               FenError::PawnOnBackRank(Position::new(0, 7)));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3 x - - 0 1"), FenError::InvalidSideToMove("x".to_string()));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), FenError::InvalidCastling("K".to_string()));
+    assert_eq!(err("4k3/8/8/8/8/8/8/R3K3 w QQ - 0 1"), FenError::InvalidCastling("QQ".to_string()));
+    assert_eq!(err("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"),
+               FenError::BadEnPassantSquare("e3".to_string()));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3 b - e3 0 1"),
+               FenError::BadEnPassantSquare("e3".to_string()));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+               FenError::InvalidHalfmoveClock("x".to_string()));
+    assert_eq!(err("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+               FenError::InvalidFullmoveNumber("0".to_string()));
+}
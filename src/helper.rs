@@ -32,7 +32,6 @@ pub fn position_free(occupancy: u64, row: i64, col: i64) -> bool {
     (occupancy & position_mask(row, col)) == 0
 }
 
-
 // A1 -> 0, ..., H8 -> 63
 pub fn encode_pos(pos: &str) -> u64 {
     let mut chars = pos.chars();
@@ -53,3 +52,33 @@ pub fn encode_pos(pos: &str) -> u64 {
     (row as u64) * 8 + col
 }
 
+
+// Number of entries of `entry_size` bytes that fit into a hash table
+// of `megabytes`, rounded down to a power of two so keys can be masked
+pub fn table_len(megabytes: usize, entry_size: usize) -> usize {
+    let max_entries = (megabytes << 20) / entry_size;
+    if max_entries < 2 { 1 } else { 1 << (63 - (max_entries as u64).leading_zeros()) }
+}
+
+// xorshift64* pseudo random number generator,
+// deterministic so magics and hash keys are the same on every run
+// See: https://en.wikipedia.org/wiki/Xorshift#xorshift*
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // Numbers with only a few bits set make better magic candidates
+    pub fn next_sparse(&mut self) -> u64 {
+        self.next_u64() & self.next_u64() & self.next_u64()
+    }
+}
@@ -0,0 +1,159 @@
+// Magic bitboards for sliding piece attacks
+//
+// For each square, the occupancy of the relevant squares
+// (see occupancy_masks.rs) is hashed with a magic multiplier
+// into an index into a table of precomputed attack sets:
+//
+//   attacks[offset + ((occupancy & mask) * magic) >> shift]
+//
+// The magics are searched for on first use, with fixed seeds,
+// which only takes a few milliseconds.
+// See: https://www.chessprogramming.org/Magic_Bitboards
+
+use std::sync::OnceLock;
+
+use board::Direction;
+use helper::Rng;
+use movegen::{shoot_ray, ROOK_DIRS, BISHOP_DIRS};
+use occupancy_masks::{rook_occupancy_mask, bishop_occupancy_mask};
+
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupancy: u64) -> usize {
+        self.offset + ((occupancy & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+pub struct MagicTable {
+    magics: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+impl MagicTable {
+    pub fn attacks(&self, square: usize, occupancy: u64) -> u64 {
+        self.attacks[self.magics[square].index(occupancy)]
+    }
+}
+
+// Attacks found by walking each ray square by square,
+// used to fill the tables and to check them
+pub fn slow_attacks(square: usize, occupancy: u64, dirs: &[Direction]) -> u64 {
+    dirs.iter().fold(0, |acc, dir| acc | shoot_ray(square, dir, occupancy))
+}
+
+// All subsets of `mask`, using the Carry-Rippler trick
+fn subsets(mask: u64) -> Vec<u64> {
+    let mut result = Vec::with_capacity(1 << mask.count_ones());
+    let mut subset = 0_u64;
+    loop {
+        result.push(subset);
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+    result
+}
+
+// Try random sparse numbers until one maps every occupancy
+// of `mask` to a slot without destructive collisions.
+// Returns the magic and the filled attack table for this square.
+pub fn find_magic(square: usize, mask: u64, dirs: &[Direction], rng: &mut Rng) -> (u64, Vec<u64>) {
+    let bits = mask.count_ones();
+    let shift = 64 - bits;
+    let occupancies = subsets(mask);
+    let attacks: Vec<u64> = occupancies.iter().map(|&occ| slow_attacks(square, occ, dirs)).collect();
+
+    // Entries are tagged with the attempt that wrote them,
+    // so the table doesn't need to be cleared between attempts
+    let mut table = vec![0_u64; 1 << bits];
+    let mut used = vec![0_usize; 1 << bits];
+
+    for attempt in 1.. {
+        let magic = rng.next_sparse();
+
+        // Quickly reject magics that don't spread the high bits
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        let ok = occupancies.iter().zip(attacks.iter()).all(|(&occ, &att)| {
+            let index = (occ.wrapping_mul(magic) >> shift) as usize;
+            if used[index] != attempt {
+                used[index] = attempt;
+                table[index] = att;
+                true
+            } else {
+                table[index] == att
+            }
+        });
+
+        if ok {
+            return (magic, table);
+        }
+    }
+
+    unreachable!()
+}
+
+// Seeds per rank that lead to magics quickly, taken from Stockfish
+const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+fn build_table<F>(dirs: &[Direction], occupancy_mask: F) -> MagicTable
+    where F: Fn(usize) -> u64 {
+    let mut magics = [Magic::default(); 64];
+    let mut attacks = Vec::new();
+
+    for (square, entry) in magics.iter_mut().enumerate() {
+        let mut rng = Rng::new(SEEDS[square / 8]);
+        let mask = occupancy_mask(square);
+        let (magic, table) = find_magic(square, mask, dirs, &mut rng);
+        *entry = Magic {
+            mask,
+            magic,
+            shift: 64 - mask.count_ones(),
+            offset: attacks.len(),
+        };
+        attacks.extend(table);
+    }
+
+    MagicTable { magics, attacks }
+}
+
+static ROOK_TABLE: OnceLock<MagicTable> = OnceLock::new();
+static BISHOP_TABLE: OnceLock<MagicTable> = OnceLock::new();
+
+pub fn rook_table() -> &'static MagicTable {
+    ROOK_TABLE.get_or_init(|| build_table(&ROOK_DIRS, rook_occupancy_mask))
+}
+
+pub fn bishop_table() -> &'static MagicTable {
+    BISHOP_TABLE.get_or_init(|| build_table(&BISHOP_DIRS, bishop_occupancy_mask))
+}
+
+pub fn rook_attacks(square: usize, occupancy: u64) -> u64 {
+    rook_table().attacks(square, occupancy)
+}
+
+pub fn bishop_attacks(square: usize, occupancy: u64) -> u64 {
+    bishop_table().attacks(square, occupancy)
+}
+
+#[test]
+fn magic_attacks_test() {
+    let mut rng = Rng::new(1);
+    for square in 0..64 {
+        for _ in 0..100 {
+            let occupancy = rng.next_sparse() | rng.next_sparse();
+            assert_eq!(rook_attacks(square, occupancy), slow_attacks(square, occupancy, &ROOK_DIRS));
+            assert_eq!(bishop_attacks(square, occupancy), slow_attacks(square, occupancy, &BISHOP_DIRS));
+        }
+    }
+}
@@ -1,18 +1,32 @@
 // TODO: Remove this once we are done
 #![allow(dead_code)]
 
-use std::fmt;
-use std::cmp;
+use std::env;
+use std::io::{self, BufRead};
+use std::iter;
+use std::thread;
 
 mod bitscan;
-use bitscan::*;
 
 mod helper;
 mod occupancy_masks;
+mod magic;
 mod movegen;
 mod board;
+mod fen;
+mod zobrist;
+mod eval;
+mod perft;
+mod search;
+mod movepick;
+mod see;
+mod tt;
+mod timeman;
+mod engine;
+mod uci;
+mod xboard;
 
-use board::{Board, Color, Position, Move};
+use board::Board;
 
 fn print_mask(mask: u64) {
     // A1 (bottom left, from whites perspective) is the LSB,
@@ -21,36 +35,76 @@ fn print_mask(mask: u64) {
         let row = (mask >> (8 * i)) & 0xff;
 
         // See: http://graphics.stanford.edu/~seander/bithacks.html
-        let row_rev = (row * 0x0202020202 & 0x010884422010) % 1023;
+        let row_rev = ((row * 0x0202020202) & 0x010884422010) % 1023;
         println!("{:08b}", row_rev);
     }
 }
 
-fn new_board(board: &Board, m: &Move) -> Board {
-    // unimplemented!();
-    // TODO: Implement board cloning
-    let mut b = (*board).clone();
-    b.make_move(m);
-    b
-}
+// Usage: chess (perft|divide|detailed) <depth> [fen] [--hash=<MB>] [--threads=<n>]
+//        chess eval [fen]
+// Without arguments, speak UCI or xboard on stdin and stdout
+fn main() {
+    let (options, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|a| a.starts_with("--"));
 
-pub fn perft(board: &Board, depth: i64) -> u64 {
-    if depth == 0 {
-        1
-    } else {
-        let mut count = 0;
-        let moves = movegen::all_moves(board, board.turn);
-        for m in moves.iter() {
-            let b = new_board(board, m);
-            count += perft(&b, depth - 1);
+    if args.is_empty() && options.is_empty() {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines().map_while(Result::ok);
+
+        // The first command tells us which protocol the GUI speaks
+        match lines.next() {
+            Some(ref first) if first.trim() == "xboard" => xboard::run(lines),
+            Some(first) => uci::run(iter::once(first).chain(lines)),
+            None => {}
         }
-        count
+        return;
     }
-}
 
-fn main() {
-    let board = Board::starting_position();
-    println!("perft 1: {}", perft(&board, 3));
+    if args.first().map(|a| a.as_str()) == Some("eval") {
+        match args.get(1..).filter(|fen| !fen.is_empty()) {
+            Some(fen) => match Board::from_fen(&fen.join(" ")) {
+                Ok(board) => println!("{}", eval::trace::trace_eval(&board)),
+                Err(err) => eprintln!("Invalid FEN: {}", err),
+            },
+            None => println!("{}", eval::trace::trace_eval(&Board::starting_position())),
+        }
+        return;
+    }
+
+    let hash_size = options.iter()
+        .filter_map(|o| o.trim_start_matches("--hash=").parse::<usize>().ok())
+        .next();
+    let threads = options.iter()
+        .filter_map(|o| o.trim_start_matches("--threads=").parse::<usize>().ok())
+        .next()
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let depth: i64 = args.get(1).and_then(|d| d.parse().ok()).unwrap_or(3);
+    let mut board = if args.len() > 2 {
+        match Board::from_fen(&args[2..].join(" ")) {
+            Ok(board) => board,
+            Err(err) => {
+                eprintln!("Invalid FEN: {}", err);
+                return;
+            }
+        }
+    } else {
+        Board::starting_position()
+    };
+
+    match args.first().map(|a| a.as_str()) {
+        Some("divide") => {
+            let divide = perft::perft_divide(&mut board, depth);
+            println!("{}", perft::divide_report(&divide));
+        }
+        Some("detailed") => {
+            println!("{}", perft::perft_detailed(&mut board, depth));
+        }
+        _ => {
+            let table = hash_size.map(perft::PerftTable::new);
+            let nodes = perft::perft_parallel(&board, depth, threads, table.as_ref());
+            println!("perft {}: {}", depth, nodes);
+        }
+    }
 
     // let moves = movegen::generate_pawn_moves_white(helper::encode_pos("E2"), occ, 0);
     // print_mask(moves);
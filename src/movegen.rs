@@ -1,260 +1,579 @@
-use board::{Board, Color, Position, Direction, Move};
+use board::{Board, Color, Position, Direction, Move, Type};
+use board::{CASTLE_WHITE_KING, CASTLE_WHITE_QUEEN, CASTLE_BLACK_KING, CASTLE_BLACK_QUEEN};
+use bitscan::BitIter;
+use magic;
 
-pub fn shoot_ray(pos_: &Position, dir: Direction, board: &Board, color: Color) -> Vec<Move> {
-    let mut pos = pos_.clone();
-    let mut result: Vec<Move> = Vec::new();
+const KNIGHT_DIRS: [Direction; 8] = [
+    Direction( 2,  1),
+    Direction( 2, -1),
+    Direction(-2,  1),
+    Direction(-2, -1),
+    Direction( 1,  2),
+    Direction( 1, -2),
+    Direction(-1,  2),
+    Direction(-1, -2),
+];
 
-    loop {
-        pos = Position::new(pos.file + dir.0, pos.rank + dir.1);
+const KING_DIRS: [Direction; 8] = [
+    Direction( 0, -1),
+    Direction( 0,  1),
+    Direction( 1,  0),
+    Direction(-1,  0),
+    Direction(-1, -1),
+    Direction(-1,  1),
+    Direction( 1, -1),
+    Direction( 1,  1),
+];
 
-        // TODO: Check occupation
-        if pos.is_valid() {
-            let piece = board.get_pos(&pos);
-            if let Some(p) = *piece {
-                if p.color != color {
-                    result.push(Move::normal(pos_, &pos));
+pub const ROOK_DIRS: [Direction; 4] = [
+    Direction( 1,  0),
+    Direction(-1,  0),
+    Direction( 0,  1),
+    Direction( 0, -1),
+];
+
+pub const BISHOP_DIRS: [Direction; 4] = [
+    Direction( 1,  1),
+    Direction( 1, -1),
+    Direction(-1,  1),
+    Direction(-1, -1),
+];
+
+// Squares reachable with a single step in one of `dirs`,
+// computed at compile time for each of the 64 squares
+const fn step_attacks(dirs: &[Direction]) -> [u64; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let file = (square % 8) as i64;
+        let rank = (square / 8) as i64;
+        let mut i = 0;
+        while i < dirs.len() {
+            let f = file + dirs[i].0;
+            let r = rank + dirs[i].1;
+            if f >= 0 && f < 8 && r >= 0 && r < 8 {
+                table[square] |= 1 << (r * 8 + f);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+// Squares strictly between two squares on a common rank, file or diagonal
+const fn between_table() -> [[u64; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+    let mut from = 0;
+    while from < 64 {
+        let mut to = 0;
+        while to < 64 {
+            let df = (to % 8) as i64 - (from % 8) as i64;
+            let dr = (to / 8) as i64 - (from / 8) as i64;
+            if (df != 0 || dr != 0) && (df == 0 || dr == 0 || df == dr || df == -dr) {
+                let step = dr.signum() * 8 + df.signum();
+                let mut square = from as i64 + step;
+                while square != to as i64 {
+                    table[from][to] |= 1 << square;
+                    square += step;
                 }
-                break;
-            } else {
-                result.push(Move::normal(pos_, &pos));
             }
-        } else {
-            break;
+            to += 1;
         }
+        from += 1;
     }
+    table
+}
 
-    result
+static KNIGHT_ATTACKS: [u64; 64] = step_attacks(&KNIGHT_DIRS);
+static KING_ATTACKS: [u64; 64] = step_attacks(&KING_DIRS);
+// Indexed by `Color as usize`, black pawns attack downwards
+static PAWN_ATTACKS: [[u64; 64]; 2] = [
+    step_attacks(&[Direction(-1, -1), Direction(1, -1)]),
+    step_attacks(&[Direction(-1,  1), Direction(1,  1)]),
+];
+static BETWEEN: [[u64; 64]; 64] = between_table();
+
+pub fn knight_attacks(square: usize) -> u64 {
+    KNIGHT_ATTACKS[square]
 }
 
-pub fn rook_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
-    let mut result: Vec<Move> = Vec::new();
+pub fn king_attacks(square: usize) -> u64 {
+    KING_ATTACKS[square]
+}
 
-    result.extend(shoot_ray(pos, Direction( 1, 0), board, color));
-    result.extend(shoot_ray(pos, Direction(-1, 0), board, color));
-    result.extend(shoot_ray(pos, Direction(0,  1), board, color));
-    result.extend(shoot_ray(pos, Direction(0, -1), board, color));
+pub fn pawn_attacks(square: usize, color: Color) -> u64 {
+    PAWN_ATTACKS[color as usize][square]
+}
 
-    result
+pub fn between(from: usize, to: usize) -> u64 {
+    BETWEEN[from][to]
 }
 
-pub fn bishop_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
-    let mut result: Vec<Move> = Vec::new();
+// Walk from `square` in direction `dir` until the edge of the board
+// or the first occupied square, which is included in the result
+pub fn shoot_ray(square: usize, dir: &Direction, occupancy: u64) -> u64 {
+    let mut result = 0;
+    let mut pos = Position::from_square(square);
+
+    loop {
+        pos = Position::new(pos.file + dir.0, pos.rank + dir.1);
+        if !pos.is_valid() {
+            break;
+        }
 
-    result.extend(shoot_ray(pos, Direction( 1,  1), board, color));
-    result.extend(shoot_ray(pos, Direction( 1, -1), board, color));
-    result.extend(shoot_ray(pos, Direction(-1,  1), board, color));
-    result.extend(shoot_ray(pos, Direction(-1, -1), board, color));
+        result |= pos.mask();
+        if occupancy & pos.mask() != 0 {
+            break;
+        }
+    }
 
     result
 }
 
-pub fn queen_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
-    let mut result: Vec<Move> = Vec::new();
+pub fn rook_attacks(square: usize, occupancy: u64) -> u64 {
+    magic::rook_attacks(square, occupancy)
+}
 
-    result.extend(rook_moves(pos, board, color));
-    result.extend(bishop_moves(pos, board, color));
+pub fn bishop_attacks(square: usize, occupancy: u64) -> u64 {
+    magic::bishop_attacks(square, occupancy)
+}
 
-    result
+pub fn queen_attacks(square: usize, occupancy: u64) -> u64 {
+    rook_attacks(square, occupancy) | bishop_attacks(square, occupancy)
 }
 
-static KNIGHT_DIRS: &'static[Direction] = &[
-    Direction( 2,  1),
-    Direction( 2, -1),
-    Direction(-2,  1),
-    Direction(-2, -1),
-    Direction( 1,  2),
-    Direction( 1, -2),
-    Direction(-1,  2),
-    Direction(-1, -2),
-];
+// All pieces of both colors that attack `square`,
+// with `occupancy` used to block sliding pieces
+pub fn attackers_to(board: &Board, square: usize, occupancy: u64) -> u64 {
+    let rooks = board.type_mask(Type::Rook) | board.type_mask(Type::Queen);
+    let bishops = board.type_mask(Type::Bishop) | board.type_mask(Type::Queen);
+
+    (pawn_attacks(square, Color::White) & board.piece_mask(Type::Pawn, Color::Black)) |
+    (pawn_attacks(square, Color::Black) & board.piece_mask(Type::Pawn, Color::White)) |
+    (knight_attacks(square) & board.type_mask(Type::Knight)) |
+    (king_attacks(square) & board.type_mask(Type::King)) |
+    (rook_attacks(square, occupancy) & rooks) |
+    (bishop_attacks(square, occupancy) & bishops)
+}
 
-static KING_DIRS: &'static[Direction] = &[
-    Direction( 0, -1),
-    Direction( 0,  1),
-    Direction( 1,  0),
-    Direction(-1,  0),
-    Direction(-1, -1),
-    Direction(-1,  1),
-    Direction( 1, -1),
-    Direction( 1,  1),
-];
+// Check if any piece of color `by` attacks `pos`
+pub fn is_attacked(pos: &Position, board: &Board, by: Color) -> bool {
+    attackers_to(board, pos.square(), board.occupancy()) & board.color_mask(by) != 0
+}
 
-pub fn is_valid_move(to: &Position, board: &Board, color: Color) -> bool {
-    if let Some(piece) = *board.get_pos(&to) {
-        piece.color != color
-    } else {
-        true
+fn moves_to(from: &Position, targets: u64) -> Vec<Move> {
+    BitIter(targets)
+        .map(|to| Move::normal(from, &Position::from_square(to)))
+        .collect()
+}
+
+fn push_moves(result: &mut Vec<Move>, from: usize, targets: u64) {
+    let from = Position::from_square(from);
+    for to in BitIter(targets) {
+        result.push(Move::normal(&from, &Position::from_square(to)));
     }
 }
 
+pub fn rook_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
+    let targets = rook_attacks(pos.square(), board.occupancy());
+    moves_to(pos, targets & !board.color_mask(color))
+}
+
+pub fn bishop_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
+    let targets = bishop_attacks(pos.square(), board.occupancy());
+    moves_to(pos, targets & !board.color_mask(color))
+}
+
+pub fn queen_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
+    let targets = queen_attacks(pos.square(), board.occupancy());
+    moves_to(pos, targets & !board.color_mask(color))
+}
+
 pub fn knight_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
-    let result: Vec<Move> =
-        KNIGHT_DIRS
-            .iter()
-            .map(|dir| Position::new(pos.file + dir.0, pos.rank + dir.1))
-            .filter(|p| p.is_valid())
-            .filter(|p| is_valid_move(p, board, color))
-            .map(|p| Move::normal(pos, &p))
-            .collect();
-    result
+    moves_to(pos, knight_attacks(pos.square()) & !board.color_mask(color))
 }
 
 pub fn king_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
-    let result: Vec<Move> =
-        KING_DIRS
-            .iter()
-            .map(|dir| Position::new(pos.file + dir.0, pos.rank + dir.1))
-            .filter(|p| p.is_valid())
-            .filter(|p| is_valid_move(p, board, color))
-            .map(|p| Move::normal(pos, &p))
-            .collect();
+    moves_to(pos, king_attacks(pos.square()) & !board.color_mask(color))
+}
+
+pub fn castling_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
+    let mut result: Vec<Move> = Vec::new();
+    let enemy = color.opponent();
+
+    let (king_side, queen_side) = if color == Color::White {
+        (CASTLE_WHITE_KING, CASTLE_WHITE_QUEEN)
+    } else {
+        (CASTLE_BLACK_KING, CASTLE_BLACK_QUEEN)
+    };
+
+    if !board.can_castle(king_side | queen_side) || is_attacked(pos, board, enemy) {
+        return result;
+    }
+
+    let rank = pos.rank;
+    let free = |file: i64| board.get_pos(&Position::new(file, rank)).is_none();
+    let safe = |file: i64| !is_attacked(&Position::new(file, rank), board, enemy);
+
+    // The squares between king and rook must be empty,
+    // the king may not pass through or land on an attacked square
+    if board.can_castle(king_side) && free(5) && free(6) && safe(5) && safe(6) {
+        result.push(Move::normal(pos, &Position::new(6, rank)));
+    }
+    if board.can_castle(queen_side) && free(1) && free(2) && free(3) && safe(2) && safe(3) {
+        result.push(Move::normal(pos, &Position::new(2, rank)));
+    }
+
     result
 }
 
+static PROMOTION_TYPES: &[Type] = &[Type::Queen, Type::Rook, Type::Bishop, Type::Knight];
+
+// Pawn moves onto the first or last rank are expanded
+// into one move per possible promotion
+fn push_pawn_moves(result: &mut Vec<Move>, from: usize, targets: u64) {
+    let from = Position::from_square(from);
+    for to in BitIter(targets) {
+        let to = Position::from_square(to);
+        if to.rank == 0 || to.rank == 7 {
+            for kind in PROMOTION_TYPES.iter() {
+                result.push(Move::promotion(&from, &to, *kind));
+            }
+        } else {
+            result.push(Move::normal(&from, &to));
+        }
+    }
+}
+
+// Pushes and regular captures of a single pawn, without en passant
+//
 // White pawns starts at rank 1 and move towards rank 7
 // If they are in rank 1, the can move 2 steps foreward (up)
 // Black pawns start at rank 6 and move towards rank 0
 // If they are in rank 6, the can move 2 steps foreward (down)
-pub fn pawn_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
-    if color == Color::White {
-        white_pawn_moves(pos, board)
+fn pawn_targets(square: usize, board: &Board, color: Color) -> u64 {
+    let empty = !board.occupancy();
+    let (one_step, start_rank) = if color == Color::White {
+        ((1 << square << 8) & empty, 1)
     } else {
-        black_pawn_moves(pos, board)
+        ((1 << square >> 8) & empty, 6)
+    };
+
+    let mut targets = one_step;
+    if one_step != 0 && square / 8 == start_rank {
+        let two_step = if color == Color::White { one_step << 8 } else { one_step >> 8 };
+        targets |= two_step & empty;
     }
+
+    targets | (pawn_attacks(square, color) & board.color_mask(color.opponent()))
 }
 
-pub fn white_pawn_moves(pos: &Position, board: &Board) -> Vec<Move> {
+fn en_passant_mask(board: &Board) -> u64 {
+    board.en_passant.map_or(0, |square| square.mask())
+}
+
+pub fn pawn_moves(pos: &Position, board: &Board, color: Color) -> Vec<Move> {
     let mut result: Vec<Move> = Vec::new();
+    let square = pos.square();
+    let en_passant = pawn_attacks(square, color) & en_passant_mask(board);
+    push_pawn_moves(&mut result, square, pawn_targets(square, board, color) | en_passant);
+    result
+}
 
-    let one_step = Position::new(pos.file, pos.rank + 1);
-    let two_step = Position::new(pos.file, pos.rank + 2);
-    if pos.rank == 1 {
-        if board.get_pos(&one_step).is_none() {
-            result.push(Move::normal(pos, &one_step));
-        }
-        if board.get_pos(&two_step).is_none() {
-            result.push(Move::normal(pos, &two_step));
-        }
-    } else {
-        // TODO: is_valid() is not really necessary,
-        // because of pawn promotions there can never be a pawn
-        // in the first or last rank
-        if one_step.is_valid() && board.get_pos(&one_step).is_none() {
-            result.push(Move::normal(pos, &one_step));
-        }
-    }
+pub fn white_pawn_moves(pos: &Position, board: &Board) -> Vec<Move> {
+    pawn_moves(pos, board, Color::White)
+}
+
+pub fn black_pawn_moves(pos: &Position, board: &Board) -> Vec<Move> {
+    pawn_moves(pos, board, Color::Black)
+}
 
-    let capture_left = Position::new(pos.file - 1, pos.rank + 1);
-    let capture_right = Position::new(pos.file + 1, pos.rank + 1);
+// Pseudo-legal moves of all non-king pieces of `color`,
+// restricted to the squares in `allowed(square)`.
+// En passant captures are not included.
+fn push_piece_moves<F>(result: &mut Vec<Move>, board: &Board, color: Color, allowed: F)
+    where F: Fn(usize) -> u64 {
+    let occupancy = board.occupancy();
+    let targets = !board.color_mask(color);
 
-    // TODO: Maybe let get_pos return some special value
-    // if the position is not valid
-    if capture_left.is_valid() {
-        if let Some(piece) = *board.get_pos(&capture_left) {
-            if piece.color == Color::Black {
-                result.push(Move::normal(pos, &capture_left));
-            }
-        } else if Some(capture_left) == board.en_passant_black {
-            result.push(Move::normal(pos, &capture_left));
-        }
+    for from in BitIter(board.piece_mask(Type::Pawn, color)) {
+        push_pawn_moves(result, from, pawn_targets(from, board, color) & allowed(from));
     }
-    if capture_right.is_valid() {
-        if let Some(piece) = *board.get_pos(&capture_right) {
-            if piece.color == Color::Black {
-                result.push(Move::normal(pos, &capture_right));
-            }
-        } else if Some(capture_right) == board.en_passant_black {
-            result.push(Move::normal(pos, &capture_right));
+    for from in BitIter(board.piece_mask(Type::Knight, color)) {
+        push_moves(result, from, knight_attacks(from) & targets & allowed(from));
+    }
+    for from in BitIter(board.piece_mask(Type::Bishop, color)) {
+        push_moves(result, from, bishop_attacks(from, occupancy) & targets & allowed(from));
+    }
+    for from in BitIter(board.piece_mask(Type::Rook, color)) {
+        push_moves(result, from, rook_attacks(from, occupancy) & targets & allowed(from));
+    }
+    for from in BitIter(board.piece_mask(Type::Queen, color)) {
+        push_moves(result, from, queen_attacks(from, occupancy) & targets & allowed(from));
+    }
+}
+
+fn en_passant_moves(board: &Board, color: Color) -> Vec<Move> {
+    let mut result: Vec<Move> = Vec::new();
+    if let Some(square) = board.en_passant {
+        // Pawns that could capture on the square are the ones
+        // an enemy pawn on it would attack
+        let pawns = pawn_attacks(square.square(), color.opponent()) &
+                    board.piece_mask(Type::Pawn, color);
+        for from in BitIter(pawns) {
+            result.push(Move::normal(&Position::from_square(from), &square));
         }
     }
+    result
+}
+
+pub fn all_moves(board: &Board, color: Color) -> Vec<Move> {
+    let mut result: Vec<Move> = Vec::with_capacity(64);
+
+    push_piece_moves(&mut result, board, color, |_| !0);
+    result.extend(en_passant_moves(board, color));
+
+    for from in BitIter(board.piece_mask(Type::King, color)) {
+        push_moves(&mut result, from, king_attacks(from) & !board.color_mask(color));
+        result.extend(castling_moves(&Position::from_square(from), board, color));
+    }
 
-    // TODO: Handle promotions
     result
 }
 
-pub fn black_pawn_moves(pos: &Position, board: &Board) -> Vec<Move> {
-    let mut result: Vec<Move> = Vec::new();
+// Ranks a pawn promotes on, for either color
+const PROMOTION_RANKS: u64 = 0xff000000000000ff;
 
-    let one_step = Position::new(pos.file, pos.rank - 1);
-    let two_step = Position::new(pos.file, pos.rank - 2);
-    if pos.rank == 6 {
-        if board.get_pos(&one_step).is_none() {
-            result.push(Move::normal(pos, &one_step));
-        }
-        if board.get_pos(&two_step).is_none() {
-            result.push(Move::normal(pos, &two_step));
-        }
-    } else {
-        // TODO: is_valid() is not really necessary,
-        // because of pawn promotions there can never be a pawn
-        // in the first or last rank
-        if one_step.is_valid() && board.get_pos(&one_step).is_none() {
-            result.push(Move::normal(pos, &one_step));
-        }
+// Pseudo-legal captures and promotions, the moves that are
+// still looked at once the search is in quiescence
+pub fn capture_moves(board: &Board, color: Color) -> Vec<Move> {
+    let mut result: Vec<Move> = Vec::with_capacity(32);
+    let enemies = board.color_mask(color.opponent());
+    let pawns = board.piece_mask(Type::Pawn, color);
+
+    push_piece_moves(&mut result, board, color, |from| {
+        if pawns & 1 << from != 0 { enemies | PROMOTION_RANKS } else { enemies }
+    });
+    result.extend(en_passant_moves(board, color));
+
+    for from in BitIter(board.piece_mask(Type::King, color)) {
+        push_moves(&mut result, from, king_attacks(from) & enemies);
     }
 
-    let capture_left = Position::new(pos.file - 1, pos.rank - 1);
-    let capture_right = Position::new(pos.file + 1, pos.rank - 1);
+    result
+}
 
-    // TODO: Maybe let get_pos return some special value
-    // if the position is not valid
-    if capture_left.is_valid() {
-        if let Some(piece) = *board.get_pos(&capture_left) {
-            if piece.color == Color::White {
-                result.push(Move::normal(pos, &capture_left));
-            }
-        } else if Some(capture_left) == board.en_passant_white {
-            result.push(Move::normal(pos, &capture_left));
+// Pieces of color `color` that are pinned to their king,
+// together with the ray (including the pinning piece) they may move on
+fn pinned_pieces(king: usize, board: &Board, color: Color) -> Vec<(usize, u64)> {
+    let enemy = color.opponent();
+    let rooks = board.type_mask(Type::Rook) | board.type_mask(Type::Queen);
+    let bishops = board.type_mask(Type::Bishop) | board.type_mask(Type::Queen);
+
+    // Enemy sliders that would attack the king on an empty board
+    let snipers = ((rook_attacks(king, 0) & rooks) | (bishop_attacks(king, 0) & bishops)) &
+                  board.color_mask(enemy);
+
+    let mut result = Vec::new();
+    for sniper in BitIter(snipers) {
+        let blockers = between(king, sniper) & board.occupancy();
+        if blockers.count_ones() == 1 && blockers & board.color_mask(color) != 0 {
+            let pinned = BitIter(blockers).next().unwrap();
+            result.push((pinned, between(king, sniper) | 1 << sniper));
         }
     }
-    if capture_right.is_valid() {
-        if let Some(piece) = *board.get_pos(&capture_right) {
-            if piece.color == Color::White {
-                result.push(Move::normal(pos, &capture_right));
+    result
+}
+
+// Legal moves for the side to move.
+//
+// Moves are generated using the checking pieces and pins,
+// so only king moves and en passant captures need a closer look:
+// The king may not step onto attacked squares (with the king removed
+// so it can't hide behind itself on a checking ray) and en passant
+// captures remove two pieces from a rank at once.
+pub fn legal_moves(board: &Board) -> Vec<Move> {
+    let color = board.turn;
+    let enemy = color.opponent();
+
+    let king = match board.king_position(color) {
+        Some(king) => king,
+        None => return all_moves(board, color),
+    };
+    let king_square = king.square();
+
+    let mut result: Vec<Move> = Vec::with_capacity(64);
+    let occupancy = board.occupancy();
+    let checkers = attackers_to(board, king_square, occupancy) & board.color_mask(enemy);
+
+    // Squares a non-king move must go to, to block or capture a single checker
+    let check_mask = match checkers.count_ones() {
+        0 => !0,
+        1 => checkers | between(king_square, BitIter(checkers).next().unwrap()),
+        _ => 0,
+    };
+
+    if check_mask != 0 {
+        let pins = pinned_pieces(king_square, board, color);
+        push_piece_moves(&mut result, board, color, |from| {
+            match pins.iter().find(|&&(p, _)| p == from) {
+                Some(&(_, ray)) => check_mask & ray,
+                None => check_mask,
+            }
+        });
+
+        for m in en_passant_moves(board, color) {
+            let mut b = *board;
+            b.make_move(&m);
+            if !b.is_in_check(color) {
+                result.push(m);
             }
-        } else if Some(capture_right) == board.en_passant_white {
-            result.push(Move::normal(pos, &capture_right));
         }
     }
 
-    // TODO: Handle promotions
+    let without_king = occupancy ^ king.mask();
+    let king_targets = BitIter(king_attacks(king_square) & !board.color_mask(color))
+        .filter(|&to| attackers_to(board, to, without_king) & board.color_mask(enemy) == 0)
+        .fold(0, |acc, to| acc | 1 << to);
+    push_moves(&mut result, king_square, king_targets);
+
+    if checkers == 0 {
+        result.extend(castling_moves(&king, board, color));
+    }
+
     result
 }
 
-use board::Type;
+// Legal move in UCI notation like "e2e4" or "a7a8q"
+pub fn find_move(board: &Board, name: &str) -> Option<Move> {
+    legal_moves(board).into_iter().find(|m| m.to_uci() == name)
+}
 
-pub fn all_moves(board: &Board, color: Color) -> Vec<Move> {
-    let mut result: Vec<Move> = Vec::new();
+#[test]
+fn castling_test() {
+    let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    let king = Position::new(4, 0);
+    assert_eq!(castling_moves(&king, &board, Color::White).len(), 2);
+
+    board.make_move(&Move::normal(&king, &Position::new(6, 0)));
+    assert_eq!(board.get_pos(&Position::new(5, 0)).unwrap().kind, Type::Rook);
+    assert!(board.get_pos(&Position::new(7, 0)).is_none());
+    assert_eq!(board.castling, CASTLE_BLACK_KING | CASTLE_BLACK_QUEEN);
+
+    // Black can't castle out of check or through the f8 square
+    let board = Board::from_fen("r3k2r/8/8/8/8/8/8/4R1K1 b kq - 0 1").unwrap();
+    assert!(castling_moves(&Position::new(4, 7), &board, Color::Black).is_empty());
+    let board = Board::from_fen("r3k2r/8/8/8/8/8/8/5RK1 b kq - 0 1").unwrap();
+    let moves = castling_moves(&Position::new(4, 7), &board, Color::Black);
+    assert_eq!(moves.len(), 1);
+    assert_eq!(moves[0].to, Position::new(2, 7));
+}
 
-    for row in 0..8 {
-        for col in 0..8 {
-            let pos = Position::new(col, row);
-            if let Some(piece) = *board.get_pos(&pos) {
-                if piece.color == color {
-                    match piece.kind {
-                        Type::Rook => {
-                            result.extend(rook_moves(&pos, board, color));
-                        },
-                        Type::Bishop => {
-                            result.extend(bishop_moves(&pos, board, color));
-                        },
-                        Type::Knight => {
-                            result.extend(knight_moves(&pos, board, color));
-                        },
-                        Type::King => {
-                            result.extend(king_moves(&pos, board, color));
-                        },
-                        Type::Queen => {
-                            result.extend(queen_moves(&pos, board, color));
-                        },
-                        Type::Pawn => {
-                            result.extend(pawn_moves(&pos, board, color));
-                        },
-                    }
-                }
-            }
-        }
-    }
+#[test]
+fn promotion_test() {
+    let mut board = Board::from_fen("r3k3/1P6/8/8/8/8/6p1/4K2R w - - 0 1").unwrap();
+    let moves = white_pawn_moves(&Position::new(1, 6), &board);
+    assert_eq!(moves.len(), 8);
+    assert!(moves.iter().all(|m| m.promotion.is_some()));
+    assert_eq!(black_pawn_moves(&Position::new(6, 1), &board).len(), 8);
+
+    board.make_move(&Move::promotion(&Position::new(1, 6), &Position::new(0, 7), Type::Knight));
+    let piece = board.get_pos(&Position::new(0, 7)).unwrap();
+    assert_eq!((piece.kind, piece.color), (Type::Knight, Color::White));
+    assert!(board.get_pos(&Position::new(1, 6)).is_none());
+}
 
-    result
+#[test]
+fn legal_moves_test() {
+    // Double check by knight and rook, only the king can move
+    let board = Board::from_fen("4k3/8/3N4/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+    let moves = legal_moves(&board);
+    assert!(board.is_in_check(Color::Black));
+    assert!(moves.iter().all(|m| m.from == Position::new(4, 7)));
+    assert_eq!(moves.len(), 3);
+
+    // The knight is pinned to the king and has no moves
+    let board = Board::from_fen("k3r3/8/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+    let moves = legal_moves(&board);
+    assert!(moves.iter().all(|m| m.from == Position::new(4, 0)));
+    assert_eq!(moves.len(), 4);
+
+    // The only way out of check besides king moves is blocking on e4
+    let board = Board::from_fen("k3r3/8/8/8/R7/8/8/4K3 w - - 0 1").unwrap();
+    let moves = legal_moves(&board);
+    assert!(board.is_in_check(Color::White));
+    assert!(board.is_square_attacked(Position::new(4, 3), Color::Black));
+    let blocks: Vec<&Move> = moves.iter().filter(|m| m.from == Position::new(0, 3)).collect();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].to, Position::new(4, 3));
+    assert_eq!(moves.len(), 5);
+
+    // A pinned rook can still move along the pin
+    let board = Board::from_fen("4r1k1/8/8/8/8/8/4R3/4K3 w - - 0 1").unwrap();
+    let moves = legal_moves(&board);
+    let rook_moves: Vec<&Move> = moves.iter().filter(|m| m.from == Position::new(4, 1)).collect();
+    assert_eq!(rook_moves.len(), 6);
+    assert!(rook_moves.iter().all(|m| m.to.file == 4));
+}
+
+#[test]
+fn en_passant_test() {
+    let e2 = Position::new(4, 1);
+    let e4 = Position::new(4, 3);
+
+    // No black pawn next to e4, so there is no en passant square
+    let mut board = Board::starting_position();
+    board.make_move(&Move::normal(&e2, &e4));
+    assert_eq!(board.en_passant, None);
+
+    // White pawn on e5, black plays d7-d5
+    let mut board = Board::from_fen("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1").unwrap();
+    board.make_move(&Move::normal(&Position::new(3, 6), &Position::new(3, 4)));
+    let d6 = Position::new(3, 5);
+    assert_eq!(board.en_passant, Some(d6));
+    let moves = white_pawn_moves(&Position::new(4, 4), &board);
+    assert!(moves.iter().any(|m| m.to == d6));
+    board.make_move(&Move::normal(&Position::new(4, 4), &d6));
+    assert!(board.get_pos(&Position::new(3, 4)).is_none());
+    assert_eq!(board.en_passant, None);
+
+    // Black pawn on b4, white plays c2-c4
+    let mut board = Board::from_fen("4k3/8/8/8/1p6/8/2P5/4K3 w - - 0 1").unwrap();
+    board.make_move(&Move::normal(&Position::new(2, 1), &Position::new(2, 3)));
+    let c3 = Position::new(2, 2);
+    assert_eq!(board.en_passant, Some(c3));
+    assert!(black_pawn_moves(&Position::new(1, 3), &board).iter().any(|m| m.to == c3));
+
+    // The square is gone after one ply
+    board.make_move(&Move::normal(&Position::new(4, 7), &Position::new(4, 6)));
+    assert_eq!(board.en_passant, None);
+    board.make_move(&Move::normal(&Position::new(4, 0), &Position::new(4, 1)));
+    assert!(black_pawn_moves(&Position::new(1, 3), &board).iter().all(|m| m.to != c3));
+
+    // Capturing en passant would expose the king along the fifth rank,
+    // so the square is dropped, both from a FEN and after the push
+    let board = Board::from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 2").unwrap();
+    assert_eq!(board.en_passant, None);
+    let mut board = Board::from_fen("8/2p5/8/KP5r/8/8/8/7k b - - 0 1").unwrap();
+    board.make_move(&Move::normal(&Position::new(2, 6), &Position::new(2, 4)));
+    assert_eq!(board.en_passant, None);
+    assert!(legal_moves(&board).iter().all(|m| m.from != Position::new(1, 4) || m.to.file == 1));
+}
+
+#[test]
+fn capture_moves_test() {
+    use perft::REFERENCE_POSITIONS;
+
+    for &(fen, _) in REFERENCE_POSITIONS.iter() {
+        let board = Board::from_fen(fen).unwrap();
+        let color = board.turn;
+        let is_capture = |m: &Move| board.get_pos(&m.to).is_some() || m.promotion.is_some() ||
+                                    Some(m.to) == board.en_passant;
+
+        let expected: Vec<Move> = all_moves(&board, color).into_iter().filter(|m| is_capture(m)).collect();
+        let captures = capture_moves(&board, color);
+        assert_eq!(captures.len(), expected.len(), "{}", fen);
+        assert!(captures.iter().all(|m| expected.contains(m)), "{}", fen);
+    }
 }
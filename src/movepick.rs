@@ -0,0 +1,280 @@
+// Move ordering
+//
+// Alpha-beta cuts off the most when the best move is searched first,
+// so moves are handed out in stages, from the most to the least
+// promising kind, and by score within each stage. Quiet moves are
+// ranked by what caused cutoffs elsewhere in the tree: killers (at the
+// same ply), counter-moves (in reply to the same move) and the history
+// of each from/to pair.
+// See: https://www.chessprogramming.org/Move_Ordering
+
+use std::mem;
+
+use board::{Board, Move, Type};
+use eval::piece_value;
+use search::MAX_PLY;
+use see;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    TtMove,
+    GoodCaptures,
+    Promotions,
+    Killers,
+    CounterMove,
+    Quiets,
+    // Captures that lose material by static exchange evaluation
+    BadCaptures,
+}
+
+// History scores stay within +-HISTORY_MAX
+const HISTORY_MAX: i32 = 16_384;
+
+pub struct History {
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    // Indexed by the from and to squares of the previous move
+    counter_moves: [[Option<Move>; 64]; 64],
+    // Indexed by `Color as usize`, from and to square
+    butterfly: [[[i32; 64]; 64]; 2],
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            killers: [[None; 2]; MAX_PLY],
+            counter_moves: [[None; 64]; 64],
+            butterfly: [[[0; 64]; 64]; 2],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::new();
+    }
+
+    // Called before every search: killers are tied to the plies of the
+    // previous search, the history is halved so recent cutoffs count more
+    pub fn age(&mut self) {
+        self.killers = [[None; 2]; MAX_PLY];
+        for score in self.butterfly.iter_mut().flat_map(|c| c.iter_mut()).flat_map(|f| f.iter_mut()) {
+            *score /= 2;
+        }
+    }
+
+    pub fn killers(&self, ply: usize) -> [Option<Move>; 2] {
+        self.killers[ply]
+    }
+
+    pub fn counter_move(&self, previous: Option<Move>) -> Option<Move> {
+        previous.and_then(|p| self.counter_moves[p.from.square()][p.to.square()])
+    }
+
+    pub fn score(&self, board: &Board, m: &Move) -> i32 {
+        self.butterfly[board.turn as usize][m.from.square()][m.to.square()]
+    }
+
+    // The quiet move `best` caused a beta cutoff, the quiet moves
+    // in `tried` were searched before it without one
+    pub fn update(&mut self, board: &Board, best: Move, tried: &[Move], depth: i64, ply: usize,
+                  previous: Option<Move>) {
+        if self.killers[ply][0] != Some(best) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(best);
+        }
+        if let Some(p) = previous {
+            self.counter_moves[p.from.square()][p.to.square()] = Some(best);
+        }
+
+        let bonus = (depth * depth).min(400) as i32;
+        self.add(board, &best, bonus);
+        for m in tried {
+            self.add(board, m, -bonus);
+        }
+    }
+
+    // Scores approach +-HISTORY_MAX, but more slowly the closer they are
+    fn add(&mut self, board: &Board, m: &Move, bonus: i32) {
+        let score = &mut self.butterfly[board.turn as usize][m.from.square()][m.to.square()];
+        *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn captured(board: &Board, m: &Move) -> Option<Type> {
+    match *board.get_pos(&m.to) {
+        Some(piece) => Some(piece.kind),
+        None if Some(m.to) == board.en_passant &&
+                board.get_pos(&m.from).is_some_and(|p| p.kind == Type::Pawn) => Some(Type::Pawn),
+        None => None,
+    }
+}
+
+pub fn is_quiet(board: &Board, m: &Move) -> bool {
+    m.promotion.is_none() && captured(board, m).is_none()
+}
+
+// Most valuable victim, least valuable attacker
+pub fn mvv_lva(board: &Board, m: &Move) -> i32 {
+    let victim = captured(board, m).map_or(0, piece_value);
+    let attacker = board.get_pos(&m.from).map_or(0, |p| piece_value(p.kind));
+    let promotion = m.promotion.map_or(0, piece_value);
+    (victim + promotion) * 16 - attacker / 100
+}
+
+const STAGES: [Stage; 7] = [
+    Stage::TtMove,
+    Stage::GoodCaptures,
+    Stage::Promotions,
+    Stage::Killers,
+    Stage::CounterMove,
+    Stage::Quiets,
+    Stage::BadCaptures,
+];
+
+// Hands out the moves of one node stage by stage, best score first.
+// The legal moves are generated up front, as the search needs them to
+// tell mate and stalemate apart, but each stage only sorts out and
+// scores its own moves once the stages before it are used up. So a
+// cutoff by the TT move saves running SEE on the captures, and one by
+// a capture saves looking up the history of the quiet moves.
+pub struct MovePicker {
+    // Moves no stage has taken yet
+    moves: Vec<Move>,
+    tt_move: Option<Move>,
+    ply: usize,
+    previous: Option<Move>,
+    // Index into `STAGES` of the stage to fill next
+    next_stage: usize,
+    // Scored moves of the current stage
+    current: Vec<(Move, i32)>,
+    // Set aside by the good captures stage
+    bad_captures: Vec<(Move, i32)>,
+}
+
+impl MovePicker {
+    pub fn new(moves: Vec<Move>, tt_move: Option<Move>, ply: usize, previous: Option<Move>) -> Self {
+        MovePicker {
+            moves,
+            tt_move,
+            ply,
+            previous,
+            next_stage: 0,
+            current: Vec::new(),
+            bad_captures: Vec::new(),
+        }
+    }
+
+    // Take the moves of the next stage out of the remaining ones and score them
+    fn fill(&mut self, board: &Board, history: &History) {
+        let stage = STAGES[self.next_stage];
+        self.next_stage += 1;
+        if stage == Stage::BadCaptures {
+            self.current = mem::take(&mut self.bad_captures);
+            return;
+        }
+
+        let killers = history.killers(self.ply);
+        let counter_move = history.counter_move(self.previous);
+        for m in mem::take(&mut self.moves) {
+            let score = match stage {
+                Stage::TtMove => if Some(m) == self.tt_move { Some(0) } else { None },
+                Stage::GoodCaptures if captured(board, &m).is_some() => {
+                    if see::see_ge(board, &m, 0) {
+                        Some(mvv_lva(board, &m))
+                    } else {
+                        self.bad_captures.push((m, mvv_lva(board, &m)));
+                        continue;
+                    }
+                }
+                Stage::GoodCaptures => None,
+                Stage::Promotions => m.promotion.map(piece_value),
+                Stage::Killers if Some(m) == killers[0] => Some(1),
+                Stage::Killers if Some(m) == killers[1] => Some(0),
+                Stage::Killers => None,
+                Stage::CounterMove => if Some(m) == counter_move { Some(0) } else { None },
+                Stage::Quiets => Some(history.score(board, &m)),
+                Stage::BadCaptures => None,
+            };
+            match score {
+                Some(score) => self.current.push((m, score)),
+                None => self.moves.push(m),
+            }
+        }
+    }
+
+    // `board` and `history` have to be the same as for the previous calls,
+    // the search may only update the history once it's done with the picker.
+    // Selection sort, as a cutoff often comes before most moves are needed.
+    pub fn next(&mut self, board: &Board, history: &History) -> Option<(Move, Stage)> {
+        while self.current.is_empty() {
+            if self.next_stage == STAGES.len() {
+                return None;
+            }
+            self.fill(board, history);
+        }
+        let index = (0..self.current.len()).min_by_key(|&i| -self.current[i].1)?;
+        let (m, _) = self.current.swap_remove(index);
+        Some((m, STAGES[self.next_stage - 1]))
+    }
+}
+
+#[test]
+fn move_picker_test() {
+    use board::Position;
+    use movegen;
+
+    let board = Board::from_fen("3qk3/1P6/8/1r1p4/8/2N5/8/R3K3 w - - 0 1").unwrap();
+    let find = |name: &str| movegen::find_move(&board, name).unwrap();
+    let previous = Move::normal(&Position::parse("e7").unwrap(), &Position::parse("e8").unwrap());
+
+    let mut history = History::new();
+    history.update(&board, find("a1a7"), &[find("a1a2")], 4, 3, None);
+    history.update(&board, find("a1a6"), &[], 2, 3, None);
+    history.update(&board, find("e1f1"), &[], 5, 7, Some(previous));
+    history.update(&board, find("c3e4"), &[], 6, 9, None);
+
+    let moves = movegen::legal_moves(&board);
+    let count = moves.len();
+    let mut picker = MovePicker::new(moves, Some(find("a1a4")), 3, Some(previous));
+    let mut order = Vec::new();
+    while let Some((m, stage)) = picker.next(&board, &history) {
+        order.push((m.to_uci(), stage));
+    }
+
+    let expected = [
+        ("a1a4", Stage::TtMove),
+        ("c3b5", Stage::GoodCaptures),
+        ("b7b8q", Stage::Promotions),
+        ("b7b8r", Stage::Promotions),
+        ("b7b8b", Stage::Promotions),
+        ("b7b8n", Stage::Promotions),
+        ("a1a6", Stage::Killers),
+        ("a1a7", Stage::Killers),
+        ("e1f1", Stage::CounterMove),
+        ("c3e4", Stage::Quiets),
+    ];
+    assert_eq!(order.len(), count);
+    for (i, &(name, stage)) in expected.iter().enumerate() {
+        assert_eq!((order[i].0.as_str(), order[i].1), (name, stage));
+    }
+    // Searched without a cutoff before, and Nxd5 Qxd5
+    assert_eq!(order[count - 2], ("a1a2".to_string(), Stage::Quiets));
+    assert_eq!(order[count - 1], ("c3d5".to_string(), Stage::BadCaptures));
+
+    // Nothing besides the TT move is scored before it has been searched
+    let mut picker = MovePicker::new(movegen::legal_moves(&board), Some(find("a1a4")), 3, None);
+    assert_eq!(picker.next(&board, &history), Some((find("a1a4"), Stage::TtMove)));
+    assert!(picker.current.is_empty() && picker.bad_captures.is_empty());
+    assert_eq!(picker.next(&board, &history), Some((find("c3b5"), Stage::GoodCaptures)));
+    assert_eq!(picker.bad_captures.len(), 1);
+
+    // Killers don't survive to the next search, the history is halved
+    history.age();
+    assert_eq!(history.killers(3), [None, None]);
+    assert_eq!(history.score(&board, &find("c3e4")), 18);
+    assert_eq!(history.counter_move(Some(previous)), Some(find("e1f1")));
+}
@@ -1,7 +1,7 @@
 use std::cmp;
 use helper;
 
-fn generate_rook_occupancy_mask(square: u64) -> u64 {
+pub fn generate_rook_occupancy_mask(square: u64) -> u64 {
     let (row, col) = helper::decode_square(square);
     let mut res = 0_u64;
 
@@ -15,19 +15,19 @@ fn generate_rook_occupancy_mask(square: u64) -> u64 {
         if c == col {
             continue;
         }
-        res |= (1 << (row * 8 + c));
+        res |= 1 << (row * 8 + c);
     }
 
     for r in 1..7 {
         if r == row {
             continue;
         }
-        res |= (1 << (r * 8 + col));
+        res |= 1 << (r * 8 + col);
     }
     res
 }
 
-fn generate_bishop_occupancy_mask(square: u64) -> u64 {
+pub fn generate_bishop_occupancy_mask(square: u64) -> u64 {
     let (row, col) = helper::decode_square(square);
     let mut res = 0_u64;
 
@@ -39,8 +39,8 @@ fn generate_bishop_occupancy_mask(square: u64) -> u64 {
         x += 1;
         y += 1;
 
-        if (x != col && y != row) {
-            res |= (1 << (y * 8 + x));
+        if x != col && y != row {
+            res |= 1 << (y * 8 + x);
         }
     }
 
@@ -56,15 +56,25 @@ fn generate_bishop_occupancy_mask(square: u64) -> u64 {
         x += 1;
         y += 1;
 
-        if (x != col_ && y != row) {
-            res |= (1 << (y * 8 + (7 - x)));
+        if x != col_ && y != row {
+            res |= 1 << (y * 8 + (7 - x));
         }
     }
 
     res
 }
 
-const rook: [u64; 64] = [
+// Squares whose occupancy matters for the attacks of a rook or bishop,
+// precomputed with the functions above
+pub fn rook_occupancy_mask(square: usize) -> u64 {
+    ROOK[square]
+}
+
+pub fn bishop_occupancy_mask(square: usize) -> u64 {
+    BISHOP[square]
+}
+
+const ROOK: [u64; 64] = [
     0x000101010101017e,
     0x000202020202027c,
     0x000404040404047a,
@@ -131,7 +141,7 @@ const rook: [u64; 64] = [
     0x7e80808080808000,
 ];
 
-const bishop: [u64; 64] = [
+const BISHOP: [u64; 64] = [
     0x0040201008040200,
     0x0000402010080400,
     0x0000004020100a00,
@@ -197,3 +207,11 @@ const bishop: [u64; 64] = [
     0x0020100804020000,
     0x0040201008040200,
 ];
+
+#[test]
+fn occupancy_mask_tables_test() {
+    for square in 0..64 {
+        assert_eq!(rook_occupancy_mask(square), generate_rook_occupancy_mask(square as u64));
+        assert_eq!(bishop_occupancy_mask(square), generate_bishop_occupancy_mask(square as u64));
+    }
+}
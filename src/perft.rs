@@ -0,0 +1,391 @@
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use board::{Board, Move, Type};
+use helper;
+use movegen;
+
+// Count the leaf nodes of the legal move tree of depth `depth`
+pub fn perft(board: &mut Board, depth: i64) -> u64 {
+    if depth <= 0 {
+        return 1;
+    }
+
+    let moves = movegen::legal_moves(board);
+
+    // Bulk counting, there is no need to make the moves of the last ply
+    if depth <= 1 {
+        return moves.len() as u64;
+    }
+
+    let mut count = 0;
+    for m in moves.iter() {
+        let undo = board.make_move(m);
+        count += perft(board, depth - 1);
+        board.unmake_move(m, &undo);
+    }
+    count
+}
+
+// Transposition table for perft, shared between threads.
+//
+// Each entry packs the node count and the depth into one word and
+// stores the hash xor'ed with it in a second one, so entries torn by
+// concurrent writes fail the verification instead of needing a lock.
+// See: https://www.chessprogramming.org/Shared_Hash_Table#Lockless
+struct PerftEntry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+    mask: usize,
+}
+
+impl PerftTable {
+    pub fn new(megabytes: usize) -> Self {
+        let size = helper::table_len(megabytes, mem::size_of::<PerftEntry>());
+
+        PerftTable {
+            entries: (0..size).map(|_| PerftEntry { key: AtomicU64::new(0), data: AtomicU64::new(0) }).collect(),
+            mask: size - 1,
+        }
+    }
+
+    fn probe(&self, hash: u64, depth: i64) -> Option<u64> {
+        let entry = &self.entries[hash as usize & self.mask];
+        let data = entry.data.load(Ordering::Relaxed);
+        if entry.key.load(Ordering::Relaxed) ^ data == hash && (data & 0xff) as i64 == depth {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    // Always replace, deeper entries are worth more
+    // but are also much rarer in a perft tree
+    fn store(&self, hash: u64, depth: i64, count: u64) {
+        let entry = &self.entries[hash as usize & self.mask];
+        let data = count << 8 | depth as u64;
+        entry.key.store(hash ^ data, Ordering::Relaxed);
+        entry.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// Like `perft`, but subtrees of transposed positions are only counted once
+pub fn perft_hashed(board: &mut Board, depth: i64, table: &PerftTable) -> u64 {
+    if depth <= 1 {
+        return perft(board, depth);
+    }
+
+    if let Some(count) = table.probe(board.hash, depth) {
+        return count;
+    }
+
+    let mut count = 0;
+    for m in movegen::legal_moves(board).iter() {
+        let undo = board.make_move(m);
+        count += perft_hashed(board, depth - 1, table);
+        board.unmake_move(m, &undo);
+    }
+
+    table.store(board.hash, depth, count);
+    count
+}
+
+// Positions to distribute between threads, expanded ply by ply
+// until there are at least `min_positions` of them
+fn split_positions(board: &Board, depth: i64, min_positions: usize) -> (Vec<Board>, i64) {
+    let mut positions = vec![*board];
+    let mut depth = depth;
+
+    while positions.len() < min_positions && depth > 1 {
+        positions = positions
+            .iter()
+            .flat_map(|b| {
+                movegen::legal_moves(b).into_iter().map(move |m| {
+                    let mut next = *b;
+                    next.make_move(&m);
+                    next
+                })
+            })
+            .collect();
+        depth -= 1;
+    }
+
+    (positions, depth)
+}
+
+// Same result as `perft`, with the work split between `threads` threads.
+// Each thread takes the next unsearched position from a shared counter,
+// so threads that get small subtrees don't sit idle.
+// If a table is given, all threads share it.
+pub fn perft_parallel(board: &Board, depth: i64, threads: usize, table: Option<&PerftTable>) -> u64 {
+    let count = |b: &Board, depth: i64| {
+        match table {
+            Some(table) => perft_hashed(&mut b.clone(), depth, table),
+            None => perft(&mut b.clone(), depth),
+        }
+    };
+
+    let threads = threads.max(1);
+    if depth <= 1 || threads == 1 {
+        return count(board, depth);
+    }
+
+    let (positions, depth) = split_positions(board, depth, threads * 8);
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut nodes = 0;
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match positions.get(i) {
+                            Some(position) => nodes += count(position, depth),
+                            None => return nodes,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers.into_iter().map(|w| w.join().unwrap()).sum()
+    })
+}
+
+// Node counts for each root move, to find the move
+// where the counts differ from those of a reference engine
+pub fn perft_divide(board: &mut Board, depth: i64) -> Vec<(Move, u64)> {
+    // There are no root moves to count without a ply to play
+    if depth <= 0 {
+        return Vec::new();
+    }
+
+    let moves = movegen::legal_moves(board);
+    moves
+        .into_iter()
+        .map(|m| {
+            let undo = board.make_move(&m);
+            let count = perft(board, depth - 1);
+            board.unmake_move(&m, &undo);
+            (m, count)
+        })
+        .collect()
+}
+
+// Formatted like the output of other engines, e.g.
+//   e2e4: 20
+//   ...
+//
+//   Nodes searched: 400
+pub fn divide_report(divide: &[(Move, u64)]) -> String {
+    let mut res = String::new();
+    for &(ref m, count) in divide.iter() {
+        res += &format!("{}: {}\n", m.to_uci(), count);
+    }
+    let total: u64 = divide.iter().map(|&(_, count)| count).sum();
+    res += &format!("\nNodes searched: {}", total);
+    res
+}
+
+// Breakdown of the moves of the last ply,
+// in the same columns as the tables on the chessprogramming wiki
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passant: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub checkmates: u64,
+}
+
+impl PerftStats {
+    fn add(&mut self, other: &PerftStats) {
+        self.nodes += other.nodes;
+        self.captures += other.captures;
+        self.en_passant += other.en_passant;
+        self.castles += other.castles;
+        self.promotions += other.promotions;
+        self.checks += other.checks;
+        self.checkmates += other.checkmates;
+    }
+}
+
+impl fmt::Display for PerftStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Nodes: {}, Captures: {}, E.p.: {}, Castles: {}, Promotions: {}, Checks: {}, Checkmates: {}",
+               self.nodes, self.captures, self.en_passant, self.castles,
+               self.promotions, self.checks, self.checkmates)
+    }
+}
+
+// Like `perft`, but without bulk counting so that
+// every move of the last ply can be classified
+pub fn perft_detailed(board: &mut Board, depth: i64) -> PerftStats {
+    let mut stats = PerftStats::default();
+    if depth <= 0 {
+        stats.nodes = 1;
+        return stats;
+    }
+
+    for m in movegen::legal_moves(board).iter() {
+        let piece = board.get_pos(&m.from).unwrap();
+        let captured = board.get_pos(&m.to).is_some();
+        let en_passant = piece.kind == Type::Pawn && Some(m.to) == board.en_passant;
+        let castle = piece.kind == Type::King && (m.to.file - m.from.file).abs() == 2;
+
+        let undo = board.make_move(m);
+        if depth <= 1 {
+            stats.nodes += 1;
+            if captured || en_passant {
+                stats.captures += 1;
+            }
+            if en_passant {
+                stats.en_passant += 1;
+            }
+            if castle {
+                stats.castles += 1;
+            }
+            if m.promotion.is_some() {
+                stats.promotions += 1;
+            }
+            if board.is_in_check(board.turn) {
+                stats.checks += 1;
+                if movegen::legal_moves(board).is_empty() {
+                    stats.checkmates += 1;
+                }
+            }
+        } else {
+            stats.add(&perft_detailed(board, depth - 1));
+        }
+        board.unmake_move(m, &undo);
+    }
+
+    stats
+}
+
+// Reference positions with node counts for depth 1, 2, ...
+// See: https://www.chessprogramming.org/Perft_Results
+pub static REFERENCE_POSITIONS: &[(&str, &[u64])] = &[
+    // Initial position
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+     &[20, 400, 8902, 197281, 4865609, 119060324]),
+    // Position 2, "Kiwipete"
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+     &[48, 2039, 97862, 4085603, 193690690]),
+    // Position 3
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+     &[14, 191, 2812, 43238, 674624, 11030083, 178633661]),
+    // Position 4
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+     &[6, 264, 9467, 422333, 15833292]),
+    // Position 4, mirrored
+    ("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+     &[6, 264, 9467, 422333, 15833292]),
+    // Position 5
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+     &[44, 1486, 62379, 2103487, 89941194]),
+    // Position 6
+    ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+     &[46, 2079, 89890, 3894594, 164075551]),
+];
+
+#[test]
+fn perft_parallel_test() {
+    for &(fen, counts) in REFERENCE_POSITIONS.iter() {
+        let board = Board::from_fen(fen).unwrap();
+        for &threads in [1, 2, 3, 8].iter() {
+            assert_eq!(perft_parallel(&board, 3, threads, None), counts[2], "{} threads, {}", threads, fen);
+        }
+        assert_eq!(perft_parallel(&board, 1, 4, None), counts[0]);
+    }
+}
+
+#[test]
+fn perft_hashed_test() {
+    // A tiny table, so that entries are replaced all the time
+    let table = PerftTable::new(0);
+    let shared = PerftTable::new(1);
+    for &(fen, counts) in REFERENCE_POSITIONS.iter() {
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(perft_hashed(&mut board, 4, &table), counts[3], "{}", fen);
+        assert_eq!(perft_hashed(&mut board, 4, &shared), counts[3], "{}", fen);
+        assert_eq!(perft_parallel(&board, 4, 4, Some(&shared)), counts[3], "{}", fen);
+    }
+}
+
+// Run perft on all reference positions up to `max_nodes` nodes,
+// or the deepest known count if `max_nodes` is `None`
+#[cfg(test)]
+fn check_reference_positions(max_nodes: Option<u64>) {
+    for &(fen, counts) in REFERENCE_POSITIONS.iter() {
+        let mut board = Board::from_fen(fen).unwrap();
+        for (depth, &expected) in counts.iter().enumerate() {
+            if max_nodes.is_some_and(|max| expected > max) {
+                break;
+            }
+            let depth = depth as i64 + 1;
+            assert_eq!(perft(&mut board, depth), expected, "perft {} of {}", depth, fen);
+        }
+    }
+}
+
+#[test]
+fn perft_test() {
+    check_reference_positions(Some(200_000));
+}
+
+// Too slow for debug builds, run in release mode with
+//   cargo test --release -- --ignored
+#[test]
+#[ignore]
+fn perft_deep_test() {
+    check_reference_positions(None);
+}
+
+#[test]
+fn perft_divide_test() {
+    let mut board = Board::starting_position();
+    let divide = perft_divide(&mut board, 3);
+    assert_eq!(divide.len(), 20);
+    assert_eq!(divide.iter().map(|&(_, count)| count).sum::<u64>(), 8902);
+
+    let report = divide_report(&divide);
+    assert!(report.lines().any(|line| line == "e2e4: 600"));
+    assert!(report.lines().any(|line| line == "g1f3: 440"));
+    assert!(report.ends_with("Nodes searched: 8902"));
+
+    assert!(perft_divide(&mut board, 0).is_empty());
+    assert!(perft_divide(&mut board, -1).is_empty());
+    assert_eq!(perft(&mut board, 0), 1);
+    assert_eq!(perft(&mut board, -1), 1);
+    assert_eq!(perft_detailed(&mut board, -1).nodes, 1);
+    assert_eq!(divide_report(&[]), "\nNodes searched: 0");
+}
+
+#[test]
+fn perft_detailed_test() {
+    let stats = |fen: &str, depth: i64| perft_detailed(&mut Board::from_fen(fen).unwrap(), depth);
+    let (start, kiwipete, position3) = (REFERENCE_POSITIONS[0].0, REFERENCE_POSITIONS[1].0,
+                                        REFERENCE_POSITIONS[2].0);
+
+    assert_eq!(stats(start, 3), PerftStats {
+        nodes: 8902, captures: 34, en_passant: 0, castles: 0, promotions: 0, checks: 12, checkmates: 0,
+    });
+    assert_eq!(stats(kiwipete, 3), PerftStats {
+        nodes: 97862, captures: 17102, en_passant: 45, castles: 3162, promotions: 0, checks: 993,
+        checkmates: 1,
+    });
+    assert_eq!(stats(position3, 4), PerftStats {
+        nodes: 43238, captures: 3348, en_passant: 123, castles: 0, promotions: 0, checks: 1680,
+        checkmates: 17,
+    });
+}
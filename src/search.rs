@@ -0,0 +1,457 @@
+// Negamax alpha-beta search with iterative deepening
+//
+// Scores are in centipawns from the point of view of the side to move.
+// Mate scores count down from `MATE` by the number of plies to the mate,
+// so shorter mates are preferred and longer ones delayed.
+// See: https://www.chessprogramming.org/Alpha-Beta
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use board::{Board, Move, Type};
+use eval::{evaluate, piece_value};
+use eval::pawns::PawnTable;
+use movegen;
+use movepick::{self, History, MovePicker};
+use see;
+use timeman::Limits;
+use tt::{Bound, TranspositionTable};
+
+pub const MATE: i32 = 32_000;
+pub const INFINITY: i32 = 32_001;
+pub const MAX_PLY: usize = 128;
+
+// Scores above this are mates found within `MAX_PLY`
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// How often the clock and the stop flag are looked at
+const CHECK_EVERY: u64 = 1024;
+
+// Captures that can't bring the score back up to alpha even with
+// this much positional compensation are skipped in quiescence
+const DELTA_MARGIN: i32 = 200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: i64,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+}
+
+// Moves to mate, negative if the side to move is getting mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+// Material won by a capture or promotion, en passant
+// captures are the only ones landing on an empty square
+fn material_gain(board: &Board, m: &Move) -> i32 {
+    let captured = match *board.get_pos(&m.to) {
+        Some(piece) => piece_value(piece.kind),
+        None if Some(m.to) == board.en_passant => piece_value(Type::Pawn),
+        None => 0,
+    };
+    let promoted = m.promotion.map_or(0, |kind| piece_value(kind) - piece_value(Type::Pawn));
+    captured + promoted
+}
+
+pub struct Search<'a> {
+    stop: &'a AtomicBool,
+    tt: &'a mut TranspositionTable,
+    history: &'a mut History,
+    pawn_table: &'a mut PawnTable,
+    limits: Limits,
+    start: Instant,
+    deadline: Option<Instant>,
+    nodes: u64,
+    // Moves from the root to the current node
+    line: Vec<Move>,
+    // Set once the search ran out of time or nodes, or was told to stop
+    aborted: bool,
+}
+
+impl<'a> Search<'a> {
+    // `time` is the time to spend on this move, see `Limits::time_for_move`
+    pub fn new(limits: &Limits, time: Option<Duration>, stop: &'a AtomicBool,
+               tt: &'a mut TranspositionTable, history: &'a mut History, pawn_table: &'a mut PawnTable) -> Self {
+        let start = Instant::now();
+        Search {
+            stop,
+            tt,
+            history,
+            pawn_table,
+            limits: limits.clone(),
+            start,
+            deadline: time.map(|t| start + t),
+            nodes: 0,
+            line: Vec::with_capacity(MAX_PLY),
+            aborted: false,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn hashfull(&self) -> u32 {
+        self.tt.hashfull()
+    }
+
+    // Search with increasing depth until a limit is hit,
+    // `report` is called after each completed iteration.
+    //
+    // The result of an interrupted iteration is thrown away,
+    // except when not even the first one could be completed.
+    pub fn iterate<F>(&mut self, board: &Board, mut report: F) -> SearchResult
+        where F: FnMut(&SearchResult, &Search) {
+        self.tt.new_search();
+        self.history.age();
+        let mut board = *board;
+        let mut result = SearchResult {
+            best_move: movegen::legal_moves(&board).into_iter().next(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+        };
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as i64).clamp(1, MAX_PLY as i64);
+        for depth in 1..=max_depth {
+            let mut pv = Vec::new();
+            let score = self.negamax(&mut board, depth, 0, -INFINITY, INFINITY, &mut pv);
+            if self.aborted {
+                break;
+            }
+
+            result = SearchResult {
+                best_move: pv.first().copied(),
+                score,
+                depth,
+                nodes: self.nodes,
+                pv,
+            };
+            report(&result, self);
+
+            // No need to look deeper if there's only one way
+            // the game can go, or a forced mate has been found
+            let mate_plies = (MATE - score.abs()) as i64;
+            if result.best_move.is_none() || (score.abs() >= MATE_BOUND && mate_plies <= depth) {
+                break;
+            }
+        }
+
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        if self.limits.nodes.is_some_and(|n| self.nodes >= n) {
+            self.aborted = true;
+        } else if self.nodes.is_multiple_of(CHECK_EVERY) {
+            self.aborted = self.stop.load(Ordering::Relaxed) ||
+                           self.deadline.is_some_and(|d| Instant::now() >= d);
+        }
+        self.aborted
+    }
+
+    // `pv` receives the principal variation below this node
+    fn negamax(&mut self, board: &mut Board, depth: i64, ply: usize,
+               mut alpha: i32, beta: i32, pv: &mut Vec<Move>) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let entry = self.tt.probe(board.hash);
+        if let Some(entry) = entry {
+            let score = entry.score(ply);
+            let usable = match entry.bound {
+                Some(Bound::Exact) => true,
+                Some(Bound::Lower) => score >= beta,
+                Some(Bound::Upper) => score <= alpha,
+                None => false,
+            };
+            // Not at the root, which has to come up with a move
+            if ply > 0 && entry.depth as i64 >= depth && usable {
+                return score;
+            }
+        }
+
+        let moves = movegen::legal_moves(board);
+        if moves.is_empty() {
+            return if board.is_in_check(board.turn) {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+
+        if ply > 0 && board.halfmove_clock >= 100 {
+            return 0;
+        }
+
+        if depth <= 0 || ply >= MAX_PLY {
+            return self.quiescence(board, ply, alpha, beta, true);
+        }
+
+        let tt_move = entry.and_then(|e| e.best_move());
+        let previous = self.line.last().copied();
+        let mut picker = MovePicker::new(moves, tt_move, ply, previous);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+        let mut quiets_tried = Vec::new();
+        while let Some((m, _)) = picker.next(board, self.history) {
+            let undo = board.make_move(&m);
+            self.line.push(m);
+            child_pv.clear();
+            let score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            self.line.pop();
+            board.unmake_move(&m, &undo);
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(m);
+                    pv.clear();
+                    pv.push(m);
+                    pv.extend_from_slice(&child_pv);
+                }
+                if score >= beta {
+                    if movepick::is_quiet(board, &m) {
+                        self.history.update(board, m, &quiets_tried, depth, ply, previous);
+                    }
+                    break;
+                }
+            }
+
+            if movepick::is_quiet(board, &m) {
+                quiets_tried.push(m);
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(board.hash, best_move, best, depth, bound, ply);
+
+        best
+    }
+
+    // Only captures and promotions are searched until the position is quiet,
+    // the side to move can always "stand pat" and take the static evaluation
+    // instead. Checks are only evaded at the first ply, where a mate can
+    // still be found, deeper in quiescence they are ignored.
+    fn quiescence(&mut self, board: &mut Board, ply: usize, mut alpha: i32, beta: i32, first: bool) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        if ply >= MAX_PLY {
+            return evaluate(board, self.pawn_table);
+        }
+
+        let us = board.turn;
+        let evasions = first && board.is_in_check(us);
+        let stand_pat = if evasions { -INFINITY } else { evaluate(board, self.pawn_table) };
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = if evasions {
+            let moves = movegen::legal_moves(board);
+            if moves.is_empty() {
+                return -MATE + ply as i32;
+            }
+            moves
+        } else {
+            movegen::capture_moves(board, us)
+        };
+        moves.sort_by_key(|m| -movepick::mvv_lva(board, m));
+
+        let mut best = stand_pat;
+        for m in moves {
+            if !evasions && stand_pat + material_gain(board, &m) + DELTA_MARGIN < alpha {
+                continue;
+            }
+            // Losing captures won't raise the score either
+            if !evasions && !see::see_ge(board, &m, 0) {
+                continue;
+            }
+
+            let undo = board.make_move(&m);
+            if !evasions && board.is_in_check(us) {
+                board.unmake_move(&m, &undo);
+                continue;
+            }
+            let score = -self.quiescence(board, ply + 1, -beta, -alpha, false);
+            board.unmake_move(&m, &undo);
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                alpha = alpha.max(score);
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[test]
+fn mate_in_test() {
+    assert_eq!(mate_in(MATE - 1), Some(1));
+    assert_eq!(mate_in(MATE - 3), Some(2));
+    assert_eq!(mate_in(-MATE + 2), Some(-1));
+    assert_eq!(mate_in(-MATE), Some(0));
+    assert_eq!(mate_in(150), None);
+}
+
+// Everything a search borrows, so tests can start one in a single line
+#[cfg(test)]
+struct Tables {
+    stop: AtomicBool,
+    tt: TranspositionTable,
+    history: History,
+    pawn_table: PawnTable,
+}
+
+#[cfg(test)]
+impl Tables {
+    fn new() -> Self {
+        Tables {
+            stop: AtomicBool::new(false),
+            tt: TranspositionTable::new(1),
+            history: History::new(),
+            pawn_table: PawnTable::new(1),
+        }
+    }
+
+    fn search(&mut self, limits: &Limits) -> Search<'_> {
+        Search::new(limits, None, &self.stop, &mut self.tt, &mut self.history, &mut self.pawn_table)
+    }
+}
+
+#[test]
+fn search_test() {
+    let search = |fen: &str, depth: i64| {
+        let board = Board::from_fen(fen).unwrap();
+        Tables::new().search(&Limits::depth(depth)).iterate(&board, |_, _| {})
+    };
+
+    // Back rank mate
+    let result = search("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", 3);
+    assert_eq!(result.best_move.unwrap().to_uci(), "a1a8");
+    assert_eq!(result.score, MATE - 1);
+    assert_eq!(result.depth, 1);
+
+    // Ladder mate in two
+    let result = search("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 5);
+    assert_eq!(mate_in(result.score), Some(2));
+    assert_eq!((result.depth, result.pv.len()), (3, 3));
+
+    // Getting mated and stalemate
+    assert_eq!(search("7k/5KQ1/8/8/8/8/8/8 b - - 0 1", 2).score, -MATE);
+    let result = search("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", 3);
+    assert_eq!((result.best_move, result.score), (None, 0));
+
+    // Take the hanging queen
+    let result = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 2);
+    assert_eq!(result.best_move.unwrap().to_uci(), "d2d5");
+    assert!(result.score > 0);
+
+    // But not a defended pawn, even at depth one,
+    // so the score is the static evaluation after the quiet move played
+    let fen = "4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1";
+    let result = search(fen, 1);
+    let best = result.best_move.unwrap();
+    assert!(best.to_uci() != "d1d5");
+    let mut board = Board::from_fen(fen).unwrap();
+    board.make_move(&best);
+    assert_eq!(result.score, -evaluate(&board, &mut PawnTable::new(1)));
+}
+
+#[test]
+fn search_limits_test() {
+    let board = Board::starting_position();
+    let mut tables = Tables::new();
+    let limits = Limits { nodes: Some(5000), ..Limits::default() };
+    let result = tables.search(&limits).iterate(&board, |_, _| {});
+    assert!(result.best_move.is_some());
+    assert!(result.nodes <= 5000);
+
+    // Stopped before the first iteration, any legal move will do
+    tables.stop.store(true, Ordering::SeqCst);
+    let result = tables.search(&Limits::default()).iterate(&board, |_, _| {});
+    assert_eq!(result.depth, 0);
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn quiescence_test() {
+    let mut tables = Tables::new();
+    let mut search = tables.search(&Limits::default());
+    let mut quiescence = |fen: &str| {
+        let mut board = Board::from_fen(fen).unwrap();
+        let score = search.quiescence(&mut board, 0, -INFINITY, INFINITY, true);
+        assert_eq!(board, Board::from_fen(fen).unwrap());
+        score
+    };
+    // Static evaluation after `moves`, for the side to move before them
+    let mut eval_pawn_table = PawnTable::new(1);
+    let mut evaluate_after = |fen: &str, moves: &[&str]| {
+        let mut board = Board::from_fen(fen).unwrap();
+        for name in moves.iter() {
+            board.make_move(&movegen::find_move(&board, name).unwrap());
+        }
+        let score = evaluate(&board, &mut eval_pawn_table);
+        if moves.len().is_multiple_of(2) { score } else { -score }
+    };
+
+    // Quiet position, the static evaluation
+    let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+    assert_eq!(quiescence(fen), evaluate_after(fen, &[]));
+    // Rook takes the knight, unless the pawn takes back
+    let fen = "4k3/8/8/2n5/8/8/8/2R1K3 w - - 0 1";
+    assert_eq!(quiescence(fen), evaluate_after(fen, &["c1c5"]));
+    let fen = "4k3/8/1p6/2n5/8/8/8/2R1K3 w - - 0 1";
+    assert_eq!(quiescence(fen), evaluate_after(fen, &[]));
+    // Qxd5 Bxd5 would lose the queen
+    let fen = "4k3/8/8/3r4/8/8/b7/3QK3 w - - 0 1";
+    assert_eq!(quiescence(fen), evaluate_after(fen, &[]));
+    // Mated at the first quiescence ply
+    assert_eq!(quiescence("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"), -MATE);
+    // In check, but the checking rook can be taken
+    let fen = "R5k1/5ppp/8/8/8/8/8/r5K1 b - - 0 1";
+    assert_eq!(quiescence(fen), evaluate_after(fen, &["a1a8"]));
+}
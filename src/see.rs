@@ -0,0 +1,132 @@
+// Static exchange evaluation
+//
+// The material balance after both sides took turns capturing on the
+// target square of a move, always with their least valuable attacker,
+// where either side may stop capturing when it would lose by going on.
+// Sliders lined up behind an attacker (x-rays) join in once the piece
+// in front of them has captured.
+// See: https://www.chessprogramming.org/Static_Exchange_Evaluation
+
+use board::{Board, Move, Type};
+use eval::piece_value;
+use movegen::{attackers_to, bishop_attacks, rook_attacks};
+
+// Cheapest first, the king only captures if nothing can take back
+const ATTACKER_ORDER: [Type; 6] = [Type::Pawn, Type::Knight, Type::Bishop, Type::Rook, Type::Queen, Type::King];
+
+pub fn see(board: &Board, m: &Move) -> i32 {
+    let from = m.from.square();
+    let to = m.to.square();
+    let mover = match *board.get_square(from) {
+        Some(piece) => piece,
+        None => return 0,
+    };
+
+    let mut occupancy = board.occupancy() ^ 1 << from;
+    let mut gain = [0; 32];
+    gain[0] = match *board.get_square(to) {
+        Some(piece) => piece_value(piece.kind),
+        None if mover.kind == Type::Pawn && Some(m.to) == board.en_passant => {
+            // The captured pawn is behind the target square
+            let captured = if to > from { to - 8 } else { to + 8 };
+            occupancy ^= 1 << captured;
+            piece_value(Type::Pawn)
+        }
+        None => 0,
+    };
+
+    // Value of the piece standing on the target square
+    let mut on_square = match m.promotion {
+        Some(kind) => {
+            gain[0] += piece_value(kind) - piece_value(Type::Pawn);
+            piece_value(kind)
+        }
+        None => piece_value(mover.kind),
+    };
+
+    let rooks = board.type_mask(Type::Rook) | board.type_mask(Type::Queen);
+    let bishops = board.type_mask(Type::Bishop) | board.type_mask(Type::Queen);
+    let mut attackers = attackers_to(board, to, occupancy) & occupancy;
+    let mut side = mover.color.opponent();
+    let mut depth = 0;
+
+    loop {
+        let ours = attackers & board.color_mask(side);
+        let (kind, square) = match ATTACKER_ORDER.iter()
+            .map(|&kind| (kind, ours & board.type_mask(kind)))
+            .find(|&(_, mask)| mask != 0) {
+            Some((kind, mask)) => (kind, mask.trailing_zeros() as usize),
+            None => break,
+        };
+        if kind == Type::King && attackers & board.color_mask(side.opponent()) != 0 {
+            break;
+        }
+
+        depth += 1;
+        gain[depth] = on_square - gain[depth - 1];
+        on_square = piece_value(kind);
+
+        // Uncover the sliders behind the capturing piece
+        occupancy ^= 1 << square;
+        if kind == Type::Pawn || kind == Type::Bishop || kind == Type::Queen {
+            attackers |= bishop_attacks(to, occupancy) & bishops;
+        }
+        if kind == Type::Rook || kind == Type::Queen {
+            attackers |= rook_attacks(to, occupancy) & rooks;
+        }
+        attackers &= occupancy;
+        side = side.opponent();
+    }
+
+    // Each side only goes on capturing if that's better than stopping
+    while depth > 0 {
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        depth -= 1;
+    }
+    gain[0]
+}
+
+// Whether the exchange started by `m` wins at least `threshold`
+pub fn see_ge(board: &Board, m: &Move, threshold: i32) -> bool {
+    see(board, m) >= threshold
+}
+
+#[test]
+fn see_test() {
+    use movegen;
+
+    let (p, n, b, r, q) = (piece_value(Type::Pawn), piece_value(Type::Knight),
+                           piece_value(Type::Bishop), piece_value(Type::Rook), piece_value(Type::Queen));
+    let positions = [
+        // Undefended pawn
+        ("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5", p),
+        // Nxe5 Nxe5 Rxe5 Bxe5 Qxe5 Qxe5, the black queen x-rays through the bishop
+        ("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5", p - n),
+        // Rook battery, the rook behind wins the pawn
+        ("4k3/4r3/8/4p3/8/8/4R3/4R1K1 w - - 0 1", "e2e5", p),
+        ("4k3/4r3/8/4p3/8/8/4R3/6K1 w - - 0 1", "e2e5", p - r),
+        // The king can't take back while the rook behind still attacks
+        ("8/8/4k3/3p4/8/3R4/8/3RK3 w - - 0 1", "d3d5", p),
+        ("8/8/4k3/3p4/8/3R4/8/4K3 w - - 0 1", "d3d5", p - r),
+        // En passant
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", p),
+        ("4k3/2b5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 0),
+        // Promotions, the rook takes the new queen
+        ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", q - p),
+        ("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", -p),
+        ("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q", r + q - p),
+        // A quiet move onto an attacked square
+        ("4k3/8/8/8/8/2p5/8/3NK3 w - - 0 1", "d1b2", -n),
+        ("4k3/8/8/8/8/2p5/8/3NK3 w - - 0 1", "d1f2", 0),
+        // Bishop takes a knight defended by a pawn
+        ("4k3/8/2p5/3n4/8/8/6B1/4K3 w - - 0 1", "g2d5", n - b),
+    ];
+
+    for &(fen, name, expected) in positions.iter() {
+        let board = Board::from_fen(fen).unwrap();
+        let m = movegen::find_move(&board, name).unwrap();
+        assert_eq!(see(&board, &m), expected, "{} {}", fen, name);
+        assert!(see_ge(&board, &m, expected));
+        assert!(!see_ge(&board, &m, expected + 1));
+    }
+}
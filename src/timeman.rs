@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use board::Color;
+
+// Limits for a single search, as given by `go` in UCI
+// or by the time control in xboard.
+// All times are in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub depth: Option<i64>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    // Remaining time and increment, indexed by `Color as usize`
+    pub time: [Option<u64>; 2],
+    pub inc: [u64; 2],
+    pub movestogo: Option<u64>,
+    pub infinite: bool,
+}
+
+// Moves we expect to still play if the time control doesn't tell us
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+impl Limits {
+    pub fn depth(depth: i64) -> Self {
+        Limits { depth: Some(depth), ..Limits::default() }
+    }
+
+    // Time to spend on the current move, `None` if the search
+    // should only stop on depth, nodes or an explicit `stop`.
+    //
+    // `overhead` is subtracted to account for the time lost
+    // between the GUI and the engine.
+    pub fn time_for_move(&self, color: Color, overhead: u64) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+
+        if let Some(movetime) = self.movetime {
+            return Some(Duration::from_millis(movetime.saturating_sub(overhead).max(1)));
+        }
+
+        let remaining = self.time[color as usize]?;
+        let inc = self.inc[color as usize];
+        let moves = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
+
+        // Use an even share of the remaining time plus most of the increment,
+        // but never so much that the next move can't be played in time
+        let share = remaining / moves + inc * 3 / 4;
+        let max = remaining.saturating_sub(overhead) * 4 / 5;
+        Some(Duration::from_millis(share.min(max).max(1)))
+    }
+}
+
+#[test]
+fn time_for_move_test() {
+    let ms = |d: Option<Duration>| d.map(|d| d.as_millis() as u64);
+
+    assert_eq!(ms(Limits::depth(5).time_for_move(Color::White, 30)), None);
+
+    let limits = Limits { movetime: Some(1000), ..Limits::default() };
+    assert_eq!(ms(limits.time_for_move(Color::Black, 30)), Some(970));
+
+    let mut limits = Limits { time: [Some(60_000), Some(30_000)], inc: [0, 1000], ..Limits::default() };
+    assert_eq!(ms(limits.time_for_move(Color::Black, 0)), Some(2000));
+    assert_eq!(ms(limits.time_for_move(Color::White, 0)), Some(1750));
+
+    // Last move before the time control, keep a safety margin
+    limits.movestogo = Some(1);
+    assert_eq!(ms(limits.time_for_move(Color::Black, 100)), Some(47_920));
+}
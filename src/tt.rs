@@ -0,0 +1,229 @@
+// Transposition table for the search
+//
+// Positions are mapped to a bucket by the low bits of their hash,
+// the upper 16 bits are kept in the entry to tell positions sharing
+// a bucket apart. Each bucket holds a few entries, when it's full
+// the least useful one (shallow or left over from an old search)
+// is replaced.
+// See: https://www.chessprogramming.org/Transposition_Table
+
+use std::mem;
+
+use board::{Move, Position, Type};
+use helper;
+use search::MATE_BOUND;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    // The score is exact
+    Exact,
+    // The search failed high, the score is at least this
+    Lower,
+    // The search failed low, the score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Entry {
+    key: u16,
+    packed_move: u16,
+    score: i16,
+    pub depth: i8,
+    pub bound: Option<Bound>,
+    age: u8,
+}
+
+impl Entry {
+    pub fn best_move(&self) -> Option<Move> {
+        unpack_move(self.packed_move)
+    }
+
+    // Mate scores are stored relative to the position,
+    // `ply` converts them back to the distance from the root
+    pub fn score(&self, ply: usize) -> i32 {
+        let score = self.score as i32;
+        if score >= MATE_BOUND {
+            score - ply as i32
+        } else if score <= -MATE_BOUND {
+            score + ply as i32
+        } else {
+            score
+        }
+    }
+}
+
+const PROMOTIONS: [Type; 4] = [Type::Queen, Type::Rook, Type::Bishop, Type::Knight];
+
+// from | to << 6 | promotion << 12, 0 is no move as a1a1 can't be played
+fn pack_move(m: Option<Move>) -> u16 {
+    m.map_or(0, |m| {
+        let promotion = m.promotion.map_or(0, |kind| 1 + PROMOTIONS.iter().position(|&p| p == kind).unwrap());
+        (m.from.square() | m.to.square() << 6 | promotion << 12) as u16
+    })
+}
+
+fn unpack_move(packed: u16) -> Option<Move> {
+    if packed == 0 {
+        return None;
+    }
+    let from = Position::from_square(packed as usize & 63);
+    let to = Position::from_square(packed as usize >> 6 & 63);
+    Some(match packed >> 12 {
+        0 => Move::normal(&from, &to),
+        p => Move::promotion(&from, &to, PROMOTIONS[p as usize - 1]),
+    })
+}
+
+const BUCKET_SIZE: usize = 3;
+
+// Aligned so a bucket never straddles two cache lines
+#[derive(Clone, Copy, Default)]
+#[repr(align(32))]
+struct Bucket {
+    entries: [Entry; BUCKET_SIZE],
+}
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    mask: usize,
+    // Incremented for every new search, to tell stale entries apart
+    age: u8,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let size = helper::table_len(megabytes, mem::size_of::<Bucket>());
+
+        TranspositionTable {
+            buckets: vec![Bucket::default(); size],
+            mask: size - 1,
+            age: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            *bucket = Bucket::default();
+        }
+        self.age = 0;
+    }
+
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        let key = (hash >> 48) as u16;
+        self.buckets[hash as usize & self.mask].entries.iter()
+            .find(|e| e.bound.is_some() && e.key == key)
+            .cloned()
+    }
+
+    // `ply` is the distance from the root, used to store mate scores
+    // relative to this position, see `Entry::score`
+    pub fn store(&mut self, hash: u64, best_move: Option<Move>, score: i32, depth: i64, bound: Bound, ply: usize) {
+        let key = (hash >> 48) as u16;
+        let age = self.age;
+        let entries = &mut self.buckets[hash as usize & self.mask].entries;
+
+        // Reuse the entry of the same position, otherwise evict the one
+        // with the lowest depth, counting entries from old searches as shallower
+        let worth = |e: &Entry| e.depth as i32 - 8 * age.wrapping_sub(e.age) as i32;
+        let index = match entries.iter().position(|e| e.bound.is_some() && e.key == key) {
+            Some(index) => index,
+            None => (0..BUCKET_SIZE).min_by_key(|&i| {
+                if entries[i].bound.is_none() { i32::MIN } else { worth(&entries[i]) }
+            }).unwrap(),
+        };
+
+        let old = entries[index];
+        // Keep the deeper result of the same search, unless this one is exact
+        if old.bound.is_some() && old.key == key && old.age == age &&
+           bound != Bound::Exact && depth < old.depth as i64 {
+            return;
+        }
+
+        let score = if score >= MATE_BOUND {
+            score + ply as i32
+        } else if score <= -MATE_BOUND {
+            score - ply as i32
+        } else {
+            score
+        };
+
+        entries[index] = Entry {
+            key,
+            // A fail low has no best move, keep the one from earlier searches
+            packed_move: if best_move.is_some() || old.key != key { pack_move(best_move) } else { old.packed_move },
+            score: score as i16,
+            depth: depth.clamp(i8::MIN as i64, i8::MAX as i64) as i8,
+            bound: Some(bound),
+            age,
+        };
+    }
+
+    // Permille of the table used by the current search,
+    // estimated from the first thousand entries
+    pub fn hashfull(&self) -> u32 {
+        let entries = self.buckets.iter().flat_map(|b| b.entries.iter()).take(1000);
+        let (used, total) = entries.fold((0, 0), |(used, total), e| {
+            (used + (e.bound.is_some() && e.age == self.age) as u32, total + 1)
+        });
+        used * 1000 / total
+    }
+}
+
+#[test]
+fn pack_move_test() {
+    let e2 = Position::parse("e2").unwrap();
+    let e4 = Position::parse("e4").unwrap();
+    let h8 = Position::parse("h8").unwrap();
+    let g7 = Position::parse("g7").unwrap();
+
+    for &m in [None, Some(Move::normal(&e2, &e4)), Some(Move::promotion(&g7, &h8, Type::Knight))].iter() {
+        assert_eq!(unpack_move(pack_move(m)), m);
+    }
+}
+
+#[test]
+fn transposition_table_test() {
+    use search::MATE;
+
+    let mut tt = TranspositionTable::new(1);
+    assert_eq!(tt.buckets.len(), 1 << 15);
+    assert_eq!(tt.hashfull(), 0);
+
+    let m = Move::normal(&Position::parse("e2").unwrap(), &Position::parse("e4").unwrap());
+    tt.store(0x1234_0000_0000_0042, Some(m), 25, 6, Bound::Exact, 3);
+    let entry = tt.probe(0x1234_0000_0000_0042).unwrap();
+    assert_eq!((entry.best_move(), entry.score(3), entry.depth, entry.bound), (Some(m), 25, 6, Some(Bound::Exact)));
+
+    // Same bucket, different verification bits
+    assert_eq!(tt.probe(0x4321_0000_0000_0042), None);
+
+    // A mate two plies after a position seen at ply 3 is three plies
+    // from the root when the same position is reached at ply 1
+    tt.store(0x1234_0000_0000_0042, None, MATE - 5, 6, Bound::Lower, 3);
+    let entry = tt.probe(0x1234_0000_0000_0042).unwrap();
+    assert_eq!((entry.score(1), entry.best_move()), (MATE - 3, Some(m)));
+
+    // A full bucket evicts the shallowest entry
+    tt.store(0x1111_0000_0000_0042, None, 0, 2, Bound::Upper, 0);
+    tt.store(0x2222_0000_0000_0042, None, 0, 9, Bound::Upper, 0);
+    tt.store(0x3333_0000_0000_0042, None, 0, 4, Bound::Upper, 0);
+    assert!(tt.probe(0x1111_0000_0000_0042).is_none());
+    assert!(tt.probe(0x2222_0000_0000_0042).is_some());
+
+    // Entries from older searches go first
+    tt.new_search();
+    tt.store(0x4444_0000_0000_0042, None, 0, 1, Bound::Upper, 0);
+    tt.new_search();
+    tt.store(0x5555_0000_0000_0042, None, 0, 1, Bound::Upper, 0);
+    assert!(tt.probe(0x4444_0000_0000_0042).is_some());
+    assert!(tt.probe(0x1234_0000_0000_0042).is_none());
+
+    // Only the last entry belongs to the current search
+    assert_eq!(tt.hashfull(), 1);
+    tt.clear();
+    assert!(tt.probe(0x4444_0000_0000_0042).is_none());
+}
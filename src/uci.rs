@@ -0,0 +1,248 @@
+// Universal Chess Interface
+// See: http://wbec-ridderkerk.nl/html/UCIProtocol.html
+
+use std::time::Duration;
+
+use board::Board;
+use engine::{self, Engine};
+use eval;
+use movegen;
+use perft;
+use search::{self, Search, SearchResult};
+use timeman::Limits;
+
+// Parse the arguments of `position`, e.g.
+//   startpos moves e2e4 e7e5
+//   fen rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 moves e7e5
+pub fn parse_position(args: &[&str]) -> Result<Board, String> {
+    let moves_at = args.iter().position(|&a| a == "moves").unwrap_or(args.len());
+
+    let mut board = match args.first() {
+        Some(&"startpos") => Board::starting_position(),
+        Some(&"fen") => Board::from_fen(&args[1..moves_at].join(" ")).map_err(|e| e.to_string())?,
+        _ => return Err("expected startpos or fen".to_string()),
+    };
+
+    for name in args.iter().skip(moves_at + 1) {
+        match movegen::find_move(&board, name) {
+            Some(m) => {
+                board.make_move(&m);
+            }
+            None => return Err(format!("illegal move {}", name)),
+        }
+    }
+
+    Ok(board)
+}
+
+// Depth of `go perft <depth>`, 1 when it's left out
+pub fn parse_perft(args: &[&str]) -> Result<i64, String> {
+    match args.get(1) {
+        None => Ok(1),
+        Some(arg) => match arg.parse() {
+            Ok(depth) if depth >= 1 => Ok(depth),
+            _ => Err(format!("invalid perft depth {}", arg)),
+        },
+    }
+}
+
+// Parse the arguments of `go`, unknown or malformed ones are ignored
+pub fn parse_go(args: &[&str]) -> Limits {
+    let mut limits = Limits::default();
+    let mut iter = args.iter();
+
+    while let Some(&arg) = iter.next() {
+        if arg == "infinite" {
+            limits.infinite = true;
+            continue;
+        }
+
+        let value = match iter.next().and_then(|v| v.parse::<i64>().ok()) {
+            Some(value) => value,
+            None => continue,
+        };
+        // Times can be negative if the GUI is late
+        let ms = value.max(0) as u64;
+
+        match arg {
+            "depth" => limits.depth = Some(value),
+            "nodes" => limits.nodes = Some(ms),
+            "movetime" => limits.movetime = Some(ms),
+            "wtime" => limits.time[1] = Some(ms),
+            "btime" => limits.time[0] = Some(ms),
+            "winc" => limits.inc[1] = ms,
+            "binc" => limits.inc[0] = ms,
+            "movestogo" => limits.movestogo = Some(ms),
+            _ => {}
+        }
+    }
+
+    limits
+}
+
+// info depth 5 score cp 30 nodes 4000 nps 80000 time 50 hashfull 12 pv e2e4 e7e5 ...
+pub fn info_line(result: &SearchResult, elapsed: Duration, hashfull: u32) -> String {
+    let score = match search::mate_in(result.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.score),
+    };
+    let ms = elapsed.as_millis() as u64;
+    let nps = result.nodes * 1000 / ms.max(1);
+
+    let mut line = format!("info depth {} score {} nodes {} nps {} time {} hashfull {}",
+                           result.depth, score, result.nodes, nps, ms, hashfull);
+    if !result.pv.is_empty() {
+        let pv: Vec<String> = result.pv.iter().map(|m| m.to_uci()).collect();
+        line += &format!(" pv {}", pv.join(" "));
+    }
+    line
+}
+
+pub fn run<I: Iterator<Item = String>>(lines: I) {
+    let mut engine = Engine::new();
+    let mut board = Board::starting_position();
+
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+
+        match command {
+            "uci" => {
+                println!("id name rust_chess");
+                println!("id author Leon Rische");
+                println!("option name Hash type spin default {} min 1 max 65536", engine::DEFAULT_HASH_SIZE);
+                println!("option name Move Overhead type spin default 30 min 0 max 5000");
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                engine.new_game();
+                board = Board::starting_position();
+            }
+            "setoption" => {
+                engine.stop();
+                set_option(&mut engine, args);
+            }
+            "position" => {
+                engine.stop();
+                match parse_position(args) {
+                    Ok(b) => board = b,
+                    Err(err) => println!("info string invalid position: {}", err),
+                }
+            }
+            "go" if args.first() == Some(&"perft") => match parse_perft(args) {
+                Ok(depth) => {
+                    let divide = perft::perft_divide(&mut board.clone(), depth);
+                    println!("{}", perft::divide_report(&divide));
+                }
+                Err(err) => println!("info string {}", err),
+            },
+            "go" => {
+                let limits = parse_go(args);
+                let info = |result: &SearchResult, search: &Search| {
+                    println!("{}", info_line(result, search.elapsed(), search.hashfull()));
+                };
+                engine.start(board, limits, info, |best| {
+                    match best {
+                        Some(m) => println!("bestmove {}", m.to_uci()),
+                        None => println!("bestmove 0000"),
+                    }
+                });
+            }
+            "stop" => engine.stop(),
+            "quit" => break,
+            // Non-standard, print the current position
+            "d" => println!("{}\nFen: {}", board, board.to_fen()),
+            // Non-standard, print the terms of the static evaluation
+            "eval" => println!("{}", eval::trace::trace_eval(&board)),
+            _ => println!("info string unknown command {}", command),
+        }
+    }
+
+    engine.stop();
+}
+
+// setoption name <name> value <value>, names may contain spaces
+fn set_option(engine: &mut Engine, args: &[&str]) {
+    let value_at = args.iter().position(|&a| a == "value").unwrap_or(args.len());
+    let name = args.get(1..value_at).map(|n| n.join(" ")).unwrap_or_default();
+    let value = args.get(value_at + 1..).map(|v| v.join(" ")).unwrap_or_default();
+
+    match name.to_lowercase().as_str() {
+        "hash" => match value.parse() {
+            Ok(megabytes) if megabytes > 0 => engine.set_hash_size(megabytes),
+            _ => println!("info string invalid value {}", value),
+        },
+        "move overhead" => match value.parse() {
+            Ok(ms) => engine.move_overhead = ms,
+            Err(_) => println!("info string invalid value {}", value),
+        },
+        _ => println!("info string unknown option {}", name),
+    }
+}
+
+#[cfg(test)]
+fn args(command: &str) -> Vec<&str> {
+    command.split(' ').collect()
+}
+
+#[test]
+fn parse_position_test() {
+    let board = parse_position(&args("startpos moves e2e4 c7c5 g1f3")).unwrap();
+    assert_eq!(board.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+    let board = parse_position(&args("fen 4k3/1P6/8/8/8/8/8/4K3 w - - 0 1 moves b7b8n e8e7")).unwrap();
+    assert_eq!(board.to_fen(), "1N6/4k3/8/8/8/8/8/4K3 w - - 1 2");
+
+    assert_eq!(parse_position(&args("startpos")).unwrap(), Board::starting_position());
+    assert!(parse_position(&args("startpos moves e2e5")).is_err());
+    assert!(parse_position(&args("fen 8/8/8 w - - 0 1")).is_err());
+    assert!(parse_position(&args("somewhere")).is_err());
+}
+
+#[test]
+fn parse_go_test() {
+    let limits = parse_go(&args("wtime 60000 btime -50 winc 1000 binc 1000 movestogo 20"));
+    assert_eq!(limits.time, [Some(0), Some(60000)]);
+    assert_eq!(limits.inc, [1000, 1000]);
+    assert_eq!(limits.movestogo, Some(20));
+    assert!(!limits.infinite);
+
+    assert_eq!(parse_go(&args("depth 7")), Limits::depth(7));
+    assert_eq!(parse_go(&args("movetime 500 nodes 10000")).movetime, Some(500));
+    assert_eq!(parse_go(&args("movetime 500 nodes 10000")).nodes, Some(10000));
+    assert!(parse_go(&args("infinite")).infinite);
+}
+
+#[test]
+fn parse_perft_test() {
+    assert_eq!(parse_perft(&args("perft 4")), Ok(4));
+    assert_eq!(parse_perft(&args("perft")), Ok(1));
+    assert!(parse_perft(&args("perft 0")).is_err());
+    assert!(parse_perft(&args("perft -2")).is_err());
+    assert!(parse_perft(&args("perft deep")).is_err());
+}
+
+#[test]
+fn info_line_test() {
+    use board::{Move, Position};
+
+    let m = |from, to| Move::normal(&Position::parse(from).unwrap(), &Position::parse(to).unwrap());
+    let mut result = SearchResult {
+        best_move: Some(m("e2", "e4")),
+        score: 35,
+        depth: 2,
+        nodes: 1200,
+        pv: vec![m("e2", "e4"), m("e7", "e5")],
+    };
+    assert_eq!(info_line(&result, Duration::from_millis(40), 3),
+               "info depth 2 score cp 35 nodes 1200 nps 30000 time 40 hashfull 3 pv e2e4 e7e5");
+
+    result.score = -search::MATE + 4;
+    result.pv.clear();
+    assert_eq!(info_line(&result, Duration::from_millis(0), 0),
+               "info depth 2 score mate -2 nodes 1200 nps 1200000 time 0 hashfull 0");
+}
@@ -0,0 +1,307 @@
+// Chess Engine Communication Protocol, as used by xboard/winboard
+// See: https://www.gnu.org/software/xboard/engine-intf.html
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use board::{Board, Color, Move, Undo};
+use engine::Engine;
+use movegen;
+use search::{self, Search, SearchResult};
+use timeman::Limits;
+
+// Moves are kept so that `undo` and `remove` can take them back.
+// The search thread plays its move on the shared game,
+// `generation` is bumped whenever a running search becomes stale.
+struct Game {
+    board: Board,
+    history: Vec<(Move, Undo)>,
+    generation: u64,
+}
+
+impl Game {
+    fn new(board: Board) -> Self {
+        Game { board, history: Vec::new(), generation: 0 }
+    }
+
+    fn play(&mut self, m: Move) {
+        let undo = self.board.make_move(&m);
+        self.history.push((m, undo));
+        if let Some(result) = game_result(&self.board) {
+            println!("{}", result);
+        }
+    }
+
+    fn take_back(&mut self) {
+        if let Some((m, undo)) = self.history.pop() {
+            self.board.unmake_move(&m, &undo);
+        }
+    }
+}
+
+fn game_result(board: &Board) -> Option<&'static str> {
+    if !movegen::legal_moves(board).is_empty() {
+        None
+    } else if !board.is_in_check(board.turn) {
+        Some("1/2-1/2 {Stalemate}")
+    } else if board.turn == Color::White {
+        Some("0-1 {Black mates}")
+    } else {
+        Some("1-0 {White mates}")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeControl {
+    // 0 for an incremental or sudden death time control
+    pub moves_per_session: u64,
+    pub increment: u64,
+    pub move_time: Option<u64>,
+    pub depth: Option<i64>,
+    // Clocks as sent by `time` and `otim`
+    pub time: Option<u64>,
+    pub opponent_time: Option<u64>,
+}
+
+impl TimeControl {
+    // level MPS BASE INC, where BASE is either minutes or minutes:seconds
+    // and INC is in seconds. The base time itself is sent with `time`.
+    pub fn parse_level(&mut self, args: &[&str]) -> Option<()> {
+        let (mps, inc) = match *args {
+            [mps, _, inc] => (mps, inc),
+            _ => return None,
+        };
+        self.moves_per_session = mps.parse().ok()?;
+        self.increment = (inc.parse::<f64>().ok()? * 1000.0) as u64;
+        self.move_time = None;
+        Some(())
+    }
+
+    // All times are in milliseconds
+    pub fn limits(&self, engine: Color, fullmove_number: u32) -> Limits {
+        let mut limits = Limits {
+            depth: self.depth,
+            movetime: self.move_time,
+            inc: [self.increment; 2],
+            ..Limits::default()
+        };
+
+        limits.time[engine as usize] = self.time;
+        limits.time[engine.opponent() as usize] = self.opponent_time;
+
+        if self.moves_per_session > 0 {
+            let played = (fullmove_number as u64).saturating_sub(1);
+            limits.movestogo = Some(self.moves_per_session - played % self.moves_per_session);
+        }
+
+        limits
+    }
+}
+
+// Thinking output as enabled by `post`: ply score time nodes pv,
+// with the time in centiseconds and mates as 100000 + moves
+pub fn post_line(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match search::mate_in(result.score) {
+        Some(moves) if moves > 0 => 100_000 + moves,
+        Some(moves) => -100_000 + moves,
+        None => result.score,
+    };
+    let pv: Vec<String> = result.pv.iter().map(|m| m.to_uci()).collect();
+    format!("{} {} {} {} {}", result.depth, score, elapsed.as_millis() / 10, result.nodes, pv.join(" "))
+}
+
+pub fn run<I: Iterator<Item = String>>(lines: I) {
+    let mut engine = Engine::new();
+    let game = Arc::new(Mutex::new(Game::new(Board::starting_position())));
+    let mut time_control = TimeControl::default();
+    let mut engine_color = Color::Black;
+    let mut force = false;
+    let mut post = false;
+
+    // Invalidate a running search, its move won't be played
+    let abort = |engine: &mut Engine| {
+        game.lock().unwrap().generation += 1;
+        engine.stop();
+    };
+
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+
+        match command {
+            "xboard" | "accepted" | "rejected" | "hard" | "easy" |
+            "random" | "computer" | "name" | "rating" => {}
+            "protover" => {
+                println!("feature myname=\"rust_chess\" ping=1 setboard=1 usermove=1 memory=1 \
+                          san=0 colors=0 analyze=0 sigint=0 sigterm=0 done=1");
+            }
+            "post" => post = true,
+            "nopost" => post = false,
+            "ping" => {
+                println!("pong {}", args.first().unwrap_or(&""));
+            }
+            "new" => {
+                abort(&mut engine);
+                engine.new_game();
+                *game.lock().unwrap() = Game::new(Board::starting_position());
+                time_control.depth = None;
+                engine_color = Color::Black;
+                force = false;
+            }
+            "force" => {
+                abort(&mut engine);
+                force = true;
+            }
+            "result" => {
+                abort(&mut engine);
+                force = true;
+            }
+            "setboard" => {
+                abort(&mut engine);
+                match Board::from_fen(&args.join(" ")) {
+                    Ok(board) => *game.lock().unwrap() = Game::new(board),
+                    Err(err) => println!("tellusererror Illegal position: {}", err),
+                }
+            }
+            "undo" | "remove" => {
+                abort(&mut engine);
+                let mut game = game.lock().unwrap();
+                game.take_back();
+                if command == "remove" {
+                    game.take_back();
+                }
+            }
+            "level" => {
+                if time_control.parse_level(args).is_none() {
+                    println!("Error (invalid time control): {}", line);
+                }
+            }
+            "st" => {
+                match args.first().and_then(|s| s.parse::<u64>().ok()) {
+                    Some(seconds) => time_control.move_time = Some(seconds * 1000),
+                    None => println!("Error (invalid time): {}", line),
+                }
+            }
+            // Hash table size in megabytes
+            "memory" => {
+                match args.first().and_then(|m| m.parse::<usize>().ok()) {
+                    Some(megabytes) if megabytes > 0 => {
+                        abort(&mut engine);
+                        engine.set_hash_size(megabytes);
+                    }
+                    _ => println!("Error (invalid memory size): {}", line),
+                }
+            }
+            "sd" => {
+                time_control.depth = args.first().and_then(|d| d.parse().ok());
+            }
+            // Clocks are sent in centiseconds
+            "time" => {
+                time_control.time = args.first().and_then(|t| t.parse::<u64>().ok()).map(|t| t * 10);
+            }
+            "otim" => {
+                time_control.opponent_time = args.first().and_then(|t| t.parse::<u64>().ok()).map(|t| t * 10);
+            }
+            "usermove" => {
+                abort(&mut engine);
+                let name = args.first().unwrap_or(&"");
+                let mut game = game.lock().unwrap();
+                match movegen::find_move(&game.board, name) {
+                    Some(m) => game.play(m),
+                    None => println!("Illegal move: {}", name),
+                }
+            }
+            "go" => {
+                abort(&mut engine);
+                force = false;
+                engine_color = game.lock().unwrap().board.turn;
+            }
+            // Move now
+            "?" => engine.stop(),
+            "quit" => break,
+            _ => println!("Error (unknown command): {}", command),
+        }
+
+        // Start thinking whenever it's our turn after a move or `go`
+        let board = game.lock().unwrap().board;
+        if (command == "go" || command == "usermove") && !force && board.turn == engine_color {
+            think(&mut engine, &game, time_control.limits(engine_color, board.fullmove_number), post);
+        }
+    }
+
+    abort(&mut engine);
+}
+
+fn think(engine: &mut Engine, game: &Arc<Mutex<Game>>, limits: Limits, post: bool) {
+    let (board, generation) = {
+        let game = game.lock().unwrap();
+        (game.board, game.generation)
+    };
+    if game_result(&board).is_some() {
+        return;
+    }
+
+    let game = game.clone();
+    let info = move |result: &SearchResult, search: &Search| {
+        if post {
+            println!("{}", post_line(result, search.elapsed()));
+        }
+    };
+    engine.start(board, limits, info, move |best| {
+        let mut game = game.lock().unwrap();
+        if game.generation != generation {
+            return;
+        }
+        if let Some(m) = best {
+            println!("move {}", m.to_uci());
+            game.play(m);
+        }
+    });
+}
+
+#[test]
+fn time_control_test() {
+    let mut tc = TimeControl::default();
+    assert_eq!(tc.parse_level(&["40", "5", "0"]), Some(()));
+    tc.time = Some(300_000);
+    tc.opponent_time = Some(250_000);
+
+    let limits = tc.limits(Color::Black, 1);
+    assert_eq!(limits.time, [Some(300_000), Some(250_000)]);
+    assert_eq!(limits.movestogo, Some(40));
+    assert_eq!(tc.limits(Color::White, 45).movestogo, Some(36));
+    assert_eq!(tc.limits(Color::White, 45).time, [Some(250_000), Some(300_000)]);
+
+    assert_eq!(tc.parse_level(&["0", "2:30", "1.5"]), Some(()));
+    let limits = tc.limits(Color::White, 10);
+    assert_eq!(limits.movestogo, None);
+    assert_eq!(limits.inc, [1500, 1500]);
+
+    tc.move_time = Some(10_000);
+    tc.depth = Some(6);
+    let limits = tc.limits(Color::White, 10);
+    assert_eq!((limits.movetime, limits.depth), (Some(10_000), Some(6)));
+
+    assert_eq!(tc.parse_level(&["40", "5"]), None);
+}
+
+#[test]
+fn post_line_test() {
+    use board::Position;
+
+    let m = |from, to| Move::normal(&Position::parse(from).unwrap(), &Position::parse(to).unwrap());
+    let mut result = SearchResult {
+        best_move: Some(m("d2", "d4")),
+        score: -20,
+        depth: 3,
+        nodes: 4500,
+        pv: vec![m("d2", "d4"), m("d7", "d5"), m("c2", "c4")],
+    };
+    assert_eq!(post_line(&result, Duration::from_millis(1234)), "3 -20 123 4500 d2d4 d7d5 c2c4");
+
+    result.score = search::MATE - 3;
+    assert_eq!(post_line(&result, Duration::from_millis(0)), "3 100002 0 4500 d2d4 d7d5 c2c4");
+}
@@ -0,0 +1,131 @@
+// Zobrist hashing
+//
+// Every feature of a position (piece on square, side to move,
+// castling rights, en passant file) gets a random 64 bit key,
+// the hash of a position is the xor of the keys of its features.
+// See: https://www.chessprogramming.org/Zobrist_Hashing
+
+use std::sync::OnceLock;
+
+use board::{Board, Color, Piece, Type};
+use helper::Rng;
+
+pub struct Keys {
+    // Indexed by `Color as usize`, `Type as usize` and square
+    pieces: [[[u64; 64]; 6]; 2],
+    black_to_move: u64,
+    // One key per combination of castling rights
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+}
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+
+fn keys() -> &'static Keys {
+    KEYS.get_or_init(|| {
+        let mut rng = Rng::new(0x1d872b41);
+        let mut keys = Keys {
+            pieces: [[[0; 64]; 6]; 2],
+            black_to_move: rng.next_u64(),
+            castling: [0; 16],
+            en_passant: [0; 8],
+        };
+
+        for key in keys.pieces.iter_mut().flat_map(|c| c.iter_mut()).flat_map(|t| t.iter_mut()) {
+            *key = rng.next_u64();
+        }
+        for key in keys.castling.iter_mut() {
+            *key = rng.next_u64();
+        }
+        for key in keys.en_passant.iter_mut() {
+            *key = rng.next_u64();
+        }
+
+        keys
+    })
+}
+
+pub fn piece(piece: Piece, square: usize) -> u64 {
+    keys().pieces[piece.color as usize][piece.kind as usize][square]
+}
+
+pub fn black_to_move() -> u64 {
+    keys().black_to_move
+}
+
+pub fn castling(rights: u8) -> u64 {
+    keys().castling[rights as usize]
+}
+
+pub fn en_passant(file: i64) -> u64 {
+    keys().en_passant[file as usize]
+}
+
+// Hash of a position computed from scratch,
+// the incrementally updated `Board::hash` must always be equal to this
+pub fn hash(board: &Board) -> u64 {
+    let mut result = castling(board.castling);
+
+    for square in 0..64 {
+        if let Some(p) = *board.get_square(square) {
+            result ^= piece(p, square);
+        }
+    }
+
+    if board.turn == Color::Black {
+        result ^= black_to_move();
+    }
+
+    if let Some(square) = board.en_passant {
+        result ^= en_passant(square.file);
+    }
+
+    result
+}
+
+// Hash of the pawns alone, see `Board::pawn_hash`
+pub fn pawn_hash(board: &Board) -> u64 {
+    (0..64).fold(0, |acc, square| match *board.get_square(square) {
+        Some(p) if p.kind == Type::Pawn => acc ^ piece(p, square),
+        _ => acc,
+    })
+}
+
+#[test]
+fn transposition_test() {
+    use board::{Move, Position};
+
+    let play = |moves: &[(&str, &str)]| {
+        let mut board = Board::starting_position();
+        for &(from, to) in moves.iter() {
+            let m = Move::normal(&Position::parse(from).unwrap(), &Position::parse(to).unwrap());
+            board.make_move(&m);
+        }
+        board
+    };
+
+    let a = play(&[("g1", "f3"), ("g8", "f6"), ("b1", "c3"), ("b8", "c6")]);
+    let b = play(&[("b1", "c3"), ("b8", "c6"), ("g1", "f3"), ("g8", "f6")]);
+    assert_eq!(a.hash, b.hash);
+    assert_eq!(a.hash, hash(&a));
+
+    // Same placement, but different castling rights or side to move
+    let c = play(&[("g1", "f3"), ("g8", "f6"), ("h1", "g1"), ("b8", "c6"), ("g1", "h1"), ("c6", "b8"),
+                   ("b1", "c3"), ("b8", "c6")]);
+    assert_eq!(a.to_fen().split(' ').next(), c.to_fen().split(' ').next());
+    assert!(a.hash != c.hash);
+    let d = Board::from_fen("r1bqkb1r/pppppppp/2n2n2/8/8/2N2N2/PPPPPPPP/R1BQKB1R b KQkq - 4 3").unwrap();
+    assert_eq!(a.hash ^ black_to_move(), d.hash);
+
+    // The en passant square is part of the key
+    let f = play(&[("e2", "e4"), ("a7", "a6"), ("e4", "e5"), ("d7", "d5")]);
+    let g = Board::from_fen("rnbqkbnr/1pp1pppp/p7/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3").unwrap();
+    assert_eq!(f.en_passant, Some(Position::parse("d6").unwrap()));
+    assert!(f.hash != g.hash);
+    assert_eq!(f.hash ^ en_passant(3), g.hash);
+
+    // Only pawns make up the pawn hash
+    assert_eq!(a.pawn_hash, Board::starting_position().pawn_hash);
+    assert_eq!(f.pawn_hash, pawn_hash(&f));
+    assert!(f.pawn_hash != a.pawn_hash);
+}